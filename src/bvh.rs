@@ -0,0 +1,418 @@
+use vek::Vec3 as TVec3;
+
+use crate::obj::Tri;
+
+type Vec3 = TVec3<f32>;
+
+/// primitives with this bit set are triangles, otherwise spheres
+pub const TRI_BIT: u32 = 1 << 31;
+/// of any leaf below the root, the traversal stack in shader.comp holds one more
+const MAX_DEPTH: usize = 64;
+const MAX_LEAF: usize = 4;
+const BINS: usize = 16;
+
+#[derive(Clone, Copy)]
+#[repr(C)]
+pub struct Node {
+    pub min: Vec3,
+    /// first primitive for leaves, right child for interior nodes, the left child always follows its parent
+    pub index: u32,
+    pub max: Vec3,
+    /// 0 for interior nodes, so leaves can't be empty
+    pub count: u32,
+}
+
+#[derive(Clone, Copy)]
+struct Bounds {
+    min: Vec3,
+    max: Vec3,
+}
+impl Bounds {
+    fn empty() -> Self {
+        Self { min: Vec3::broadcast(f32::INFINITY), max: Vec3::broadcast(f32::NEG_INFINITY) }
+    }
+    fn union(self, other: Self) -> Self {
+        Self { min: Vec3::partial_min(self.min, other.min), max: Vec3::partial_max(self.max, other.max) }
+    }
+    fn grow(self, p: Vec3) -> Self {
+        Self { min: Vec3::partial_min(self.min, p), max: Vec3::partial_max(self.max, p) }
+    }
+    fn area(&self) -> f32 {
+        let d = self.max - self.min;
+        if d.x < 0.0 {
+            return 0.0;
+        }
+        2.0 * (d.x * d.y + d.y * d.z + d.z * d.x)
+    }
+}
+
+struct PrimRef {
+    prim: u32,
+    bounds: Bounds,
+    centroid: Vec3,
+    /// index into the refit subtrees, which are grafted in whole instead of `prim`
+    subtree: Option<usize>,
+}
+
+pub struct Bvh {
+    pub nodes: Vec<Node>,
+    pub prims: Vec<u32>,
+}
+impl Bvh {
+    /// builds a binned SAH tree over `spheres` (`vec4(center, r)`) and `tris`
+    ///
+    /// `subtrees` are prebuilt trees over the triangles starting at their offset, like the model space bvh of a cached
+    /// mesh. their bounds are refit to where the triangles are now and each is grafted in as a single primitive, unless
+    /// that would put its leaves deeper than `MAX_DEPTH`
+    pub fn build(spheres: &[[f32; 4]], tris: &[Tri], subtrees: &[(usize, Bvh)]) -> Self {
+        let mut subtrees: Vec<(usize, Bvh)> = subtrees.iter().filter(|(_, x)| !x.prims.is_empty()).map(|(offset, x)| (*offset, x.refit(&tris[*offset..]))).collect();
+        loop {
+            match Self::build_with(spheres, tris, &subtrees) {
+                Ok(bvh) => {
+                    assert!(bvh.depth() <= MAX_DEPTH, "the bvh is {} deep but the shader only has a stack for {MAX_DEPTH}", bvh.depth());
+                    return bvh;
+                },
+                // its triangles go into the tree like any others instead
+                Err(i) => {
+                    subtrees.remove(i);
+                },
+            }
+        }
+    }
+    /// `Err` with the subtree that would be grafted too deep
+    fn build_with(spheres: &[[f32; 4]], tris: &[Tri], subtrees: &[(usize, Bvh)]) -> Result<Self, usize> {
+        let mut covered = vec![false; tris.len()];
+        for (offset, x) in subtrees {
+            x.prims.iter().for_each(|&p| covered[offset + (p & !TRI_BIT) as usize] = true);
+        }
+
+        let mut refs: Vec<PrimRef> = spheres.iter().enumerate().map(|(i, s)| {
+            let c = Vec3::new(s[0], s[1], s[2]);
+            let r = Vec3::broadcast(s[3].abs());
+            PrimRef { prim: i as u32, bounds: Bounds { min: c - r, max: c + r }, centroid: c, subtree: None }
+        }).collect();
+        refs.extend(tris.iter().enumerate().filter(|(i, _)| !covered[*i]).map(|(i, t)| {
+            let bounds = Bounds::empty().grow(t.a).grow(t.b).grow(t.c);
+            PrimRef { prim: i as u32 | TRI_BIT, bounds, centroid: (t.a + t.b + t.c) / 3.0, subtree: None }
+        }));
+        refs.extend(subtrees.iter().enumerate().map(|(i, (_, x))| {
+            let bounds = Bounds { min: x.nodes[0].min, max: x.nodes[0].max };
+            PrimRef { prim: 0, bounds, centroid: (bounds.min + bounds.max) / 2.0, subtree: Some(i) }
+        }));
+
+        // the shader checks for primitives before traversing, so this root only keeps the buffer from being empty
+        if refs.is_empty() {
+            let bounds = Bounds::empty();
+            return Ok(Self { nodes: vec![Node { min: bounds.min, index: 0, max: bounds.max, count: 0 }], prims: vec![] });
+        }
+        let mut bvh = Self { nodes: Vec::with_capacity(refs.len() * 2), prims: Vec::with_capacity(tris.len() + spheres.len()) };
+        let len = refs.len();
+        bvh.build_node(&mut refs, subtrees, 0, len, 0)?;
+        Ok(bvh)
+    }
+    fn build_node(&mut self, refs: &mut [PrimRef], subtrees: &[(usize, Bvh)], start: usize, end: usize, depth: usize) -> Result<usize, usize> {
+        if let (1, Some(i)) = (end - start, refs[start].subtree) {
+            return if depth + subtrees[i].1.depth() <= MAX_DEPTH { Ok(self.graft(&subtrees[i])) } else { Err(i) };
+        }
+        let bounds = refs[start..end].iter().fold(Bounds::empty(), |b, x| b.union(x.bounds));
+        let node = self.nodes.len();
+        self.nodes.push(Node { min: bounds.min, index: 0, max: bounds.max, count: 0 });
+
+        let count = end - start;
+        let mut mid = start;
+        if count > MAX_LEAF && depth < MAX_DEPTH {
+            if let Some((axis, pos)) = find_split(&refs[start..end], bounds.area()) {
+                for i in start..end {
+                    if refs[i].centroid[axis] < pos {
+                        refs.swap(i, mid);
+                        mid += 1;
+                    }
+                }
+            }
+        }
+        // subtrees can't be in a leaf, so keep splitting until they are on their own
+        if (mid == start || mid == end) && refs[start..end].iter().any(|x| x.subtree.is_some()) {
+            mid = start + count / 2;
+        }
+        if mid == start || mid == end {
+            self.nodes[node].index = self.prims.len() as u32;
+            self.nodes[node].count = count as u32;
+            self.prims.extend(refs[start..end].iter().map(|x| x.prim));
+        } else {
+            self.build_node(refs, subtrees, start, mid, depth + 1)?;
+            let right = self.build_node(refs, subtrees, mid, end, depth + 1)?;
+            self.nodes[node].index = right as u32;
+        }
+        Ok(node)
+    }
+    /// of the deepest leaf below the root, children always come after their parent so one pass sees every parent first
+    fn depth(&self) -> usize {
+        let mut depths = vec![0; self.nodes.len()];
+        for (i, node) in self.nodes.iter().enumerate() {
+            if node.count == 0 && !self.prims.is_empty() {
+                (depths[i + 1], depths[node.index as usize]) = (depths[i] + 1, depths[i] + 1);
+            }
+        }
+        depths.into_iter().max().unwrap_or(0)
+    }
+    /// appends a tree over the triangles starting at `offset`, returning its root
+    fn graft(&mut self, (offset, tree): &(usize, Bvh)) -> usize {
+        let (root, first) = (self.nodes.len(), self.prims.len());
+        self.nodes.extend(tree.nodes.iter().map(|&x| Node { index: x.index + (if x.count == 0 { root } else { first }) as u32, ..x }));
+        self.prims.extend(tree.prims.iter().map(|&x| ((x & !TRI_BIT) + *offset as u32) | TRI_BIT));
+        root
+    }
+    /// the same tree with its bounds recomputed for `tris`, since children always come after their parent a reverse
+    /// pass sees every child first
+    fn refit(&self, tris: &[Tri]) -> Self {
+        let mut nodes = self.nodes.clone();
+        for i in (0..nodes.len()).rev() {
+            let node = nodes[i];
+            let bounds = if node.count == 0 {
+                let (left, right) = (nodes[i + 1], nodes[node.index as usize]);
+                Bounds { min: left.min, max: left.max }.union(Bounds { min: right.min, max: right.max })
+            } else {
+                self.prims[node.index as usize..(node.index + node.count) as usize].iter().map(|&p| &tris[(p & !TRI_BIT) as usize]).fold(Bounds::empty(), |b, t| b.grow(t.a).grow(t.b).grow(t.c))
+            };
+            (nodes[i].min, nodes[i].max) = (bounds.min, bounds.max);
+        }
+        Self { nodes, prims: self.prims.clone() }
+    }
+    /// closest hit as `(t, prim)`, traversed the same way as `trace` in shader.comp
+    #[cfg(test)]
+    fn intersect(&self, spheres: &[[f32; 4]], tris: &[Tri], org: Vec3, dir: Vec3, t_min: f32, t_max: f32) -> Option<(f32, u32)> {
+        if self.prims.is_empty() {
+            return None;
+        }
+        let inv = dir.map(|x| 1.0 / x);
+        let mut closest: Option<(f32, u32)> = None;
+        let mut stack = vec![0];
+        while let Some(i) = stack.pop() {
+            let node = self.nodes[i];
+            let t = closest.map_or(t_max, |x| x.0);
+            if !hit_aabb(node.min, node.max, org, inv, t) {
+                continue;
+            }
+            if node.count == 0 {
+                stack.push(node.index as usize);
+                stack.push(i + 1);
+                continue;
+            }
+            for &prim in &self.prims[node.index as usize..(node.index + node.count) as usize] {
+                let t = closest.map_or(t_max, |x| x.0);
+                if let Some(hit) = hit_prim(spheres, tris, prim, org, dir, t_min, t) {
+                    closest = Some((hit, prim));
+                }
+            }
+        }
+        closest
+    }
+}
+
+fn find_split(refs: &[PrimRef], area: f32) -> Option<(usize, f32)> {
+    let centroids = refs.iter().fold(Bounds::empty(), |b, x| b.grow(x.centroid));
+    let mut best: Option<(usize, f32)> = None;
+    let mut best_cost = refs.len() as f32 * area;
+    for axis in 0..3 {
+        let (lo, hi) = (centroids.min[axis], centroids.max[axis]);
+        if hi - lo <= f32::EPSILON {
+            continue;
+        }
+        let scale = BINS as f32 / (hi - lo);
+        let mut bins = [(Bounds::empty(), 0usize); BINS];
+        for x in refs {
+            let b = (((x.centroid[axis] - lo) * scale) as usize).min(BINS - 1);
+            bins[b].0 = bins[b].0.union(x.bounds);
+            bins[b].1 += 1;
+        }
+        // sweep from the right so every split plane knows the cost of both sides
+        let mut right = [(0.0, 0usize); BINS];
+        let mut acc = (Bounds::empty(), 0);
+        for i in (1..BINS).rev() {
+            acc = (acc.0.union(bins[i].0), acc.1 + bins[i].1);
+            right[i] = (acc.0.area(), acc.1);
+        }
+        let mut left = (Bounds::empty(), 0);
+        for i in 1..BINS {
+            left = (left.0.union(bins[i - 1].0), left.1 + bins[i - 1].1);
+            if left.1 == 0 || right[i].1 == 0 {
+                continue;
+            }
+            let cost = left.0.area() * left.1 as f32 + right[i].0 * right[i].1 as f32;
+            if cost < best_cost {
+                best_cost = cost;
+                best = Some((axis, lo + i as f32 / scale));
+            }
+        }
+    }
+    best
+}
+
+#[cfg(test)]
+fn hit_aabb(min: Vec3, max: Vec3, org: Vec3, inv: Vec3, t_max: f32) -> bool {
+    let t0 = (min - org) * inv;
+    let t1 = (max - org) * inv;
+    let near = Vec3::partial_min(t0, t1).reduce_partial_max();
+    let far = Vec3::partial_max(t0, t1).reduce_partial_min();
+    near <= far && far >= 0.0 && near <= t_max
+}
+
+#[cfg(test)]
+fn hit_prim(spheres: &[[f32; 4]], tris: &[Tri], prim: u32, org: Vec3, dir: Vec3, t_min: f32, t_max: f32) -> Option<f32> {
+    if prim & TRI_BIT != 0 {
+        hit_tri(&tris[(prim & !TRI_BIT) as usize], org, dir, t_min, t_max)
+    } else {
+        hit_sphere(spheres[prim as usize], org, dir, t_min, t_max)
+    }
+}
+
+#[cfg(test)]
+fn hit_sphere(sphere: [f32; 4], org: Vec3, dir: Vec3, t_min: f32, t_max: f32) -> Option<f32> {
+    let oc = org - Vec3::new(sphere[0], sphere[1], sphere[2]);
+    let a = dir.dot(dir);
+    let h = dir.dot(oc);
+    let c = oc.dot(oc) - sphere[3] * sphere[3];
+    let discriminant = h * h - a * c;
+    if discriminant < 0.0 {
+        return None;
+    }
+    let sqrtd = discriminant.sqrt();
+    [(-h - sqrtd) / a, (-h + sqrtd) / a].into_iter().find(|&t| t_min < t && t < t_max)
+}
+
+#[cfg(test)]
+fn hit_tri(tri: &Tri, org: Vec3, dir: Vec3, t_min: f32, t_max: f32) -> Option<f32> {
+    let edge1 = tri.b - tri.a;
+    let edge2 = tri.c - tri.a;
+    let pvec = dir.cross(edge2);
+    let det = edge1.dot(pvec);
+    if det.abs() <= 1e-7 * edge1.magnitude() * edge2.magnitude() {
+        return None;
+    }
+    let invdet = 1.0 / det;
+    let tvec = org - tri.a;
+    let u = tvec.dot(pvec) * invdet;
+    if !(0.0..=1.0).contains(&u) {
+        return None;
+    }
+    let qvec = tvec.cross(edge1);
+    let v = dir.dot(qvec) * invdet;
+    if v < 0.0 || u + v > 1.0 {
+        return None;
+    }
+    let t = edge2.dot(qvec) * invdet;
+    (t_min < t && t < t_max).then_some(t)
+}
+
+#[cfg(test)]
+mod tests {
+    use rand::{rngs::StdRng, Rng, SeedableRng};
+
+    use super::*;
+
+    /// a grid of spheres with varying radii
+    fn spheres() -> Vec<[f32; 4]> {
+        (0..300).map(|i| [(i % 10) as f32 * 2.0, (i / 10 % 10) as f32 * 2.0, (i / 100) as f32 * 2.0, 0.3 + (i % 7) as f32 * 0.1]).collect()
+    }
+
+    /// small triangles scattered through a box, deterministic so failures reproduce
+    fn tris(seed: u64, count: usize) -> Vec<Tri> {
+        let mut rng = StdRng::seed_from_u64(seed);
+        let n = Vec3::unit_y();
+        (0..count).map(|_| {
+            let a = Vec3::new(rng.gen(), rng.gen(), rng.gen()) * 20.0;
+            let mut near = || a + (Vec3::new(rng.gen(), rng.gen(), rng.gen()) - 0.5) * 2.0;
+            Tri::new(a, near(), near(), n, n, n)
+        }).collect()
+    }
+
+    /// asserts that `intersect` agrees with testing every primitive for `rays` rays starting inside the scene
+    fn check(bvh: &Bvh, spheres: &[[f32; 4]], tris: &[Tri], rays: usize) {
+        let mut rng = StdRng::seed_from_u64(7);
+        let count = spheres.len() + tris.len();
+        let root = bvh.nodes[0];
+        for _ in 0..rays {
+            let org = root.min + (root.max - root.min) * Vec3::new(rng.gen(), rng.gen(), rng.gen());
+            let dir = (Vec3::new(rng.gen(), rng.gen(), rng.gen()) - 0.5).normalized();
+            let mut brute: Option<f32> = None;
+            for i in 0..count {
+                let prim = if i < spheres.len() { i as u32 } else { (i - spheres.len()) as u32 | TRI_BIT };
+                brute = hit_prim(spheres, tris, prim, org, dir, 0.001, brute.unwrap_or(1000.0)).or(brute);
+            }
+            let hit = bvh.intersect(spheres, tris, org, dir, 0.001, 1000.0).map(|x| x.0);
+            // compare distances rather than ids since primitives sharing an edge can tie
+            let same = match (hit, brute) {
+                (Some(a), Some(b)) => (a - b).abs() <= 1e-4 * a.max(1.0),
+                (a, b) => a == b,
+            };
+            assert!(same, "bvh hit {hit:?} but brute force hit {brute:?} for ray {org} {dir}");
+        }
+    }
+
+    #[test]
+    fn deep_subtrees_are_rebuilt() {
+        // a chain with a leaf on every level, which is as deep as allowed on its own and too deep below another node
+        let tris: Vec<Tri> = (0..=MAX_DEPTH).map(|i| {
+            let a = Vec3::new(i as f32, 0.0, 0.0);
+            Tri::new(a, a + Vec3::unit_y(), a + Vec3::unit_z(), Vec3::unit_x(), Vec3::unit_x(), Vec3::unit_x())
+        }).collect();
+        let chain = || {
+            let mut chain = Bvh { nodes: vec![], prims: (0..=MAX_DEPTH as u32).map(|i| i | TRI_BIT).collect() };
+            for i in 0..MAX_DEPTH as u32 {
+                chain.nodes.push(Node { min: Vec3::zero(), index: 2 * i + 2, max: Vec3::zero(), count: 0 });
+                chain.nodes.push(Node { min: Vec3::zero(), index: i, max: Vec3::zero(), count: 1 });
+            }
+            chain.nodes.push(Node { min: Vec3::zero(), index: MAX_DEPTH as u32, max: Vec3::zero(), count: 1 });
+            chain
+        };
+        assert_eq!(chain().depth(), MAX_DEPTH);
+        let alone = Bvh::build(&[], &tris, &[(0, chain())]);
+        assert_eq!(alone.nodes.len(), chain().nodes.len());
+        check(&alone, &[], &tris, 200);
+
+        let spheres = spheres();
+        let bvh = Bvh::build(&spheres, &tris, &[(0, chain())]);
+        check(&bvh, &spheres, &tris, 1000);
+    }
+
+    #[test]
+    fn tiny_triangles() {
+        // a triangle with edges of 0.05mm, in metres
+        let n = Vec3::unit_z();
+        let tri = Tri::new(Vec3::zero(), Vec3::new(5e-5, 0.0, 0.0), Vec3::new(0.0, 5e-5, 0.0), n, n, n);
+        let hit = hit_tri(&tri, Vec3::new(1e-5, 1e-5, 1.0), -Vec3::unit_z(), 0.001, 1000.0);
+        assert!(hit.is_some_and(|t| (t - 1.0).abs() < 1e-6), "{hit:?}");
+        // edge on it really is degenerate
+        assert_eq!(hit_tri(&tri, Vec3::new(-1.0, 1e-5, 0.0), Vec3::unit_x(), 0.001, 1000.0), None);
+    }
+
+    #[test]
+    fn empty_scene() {
+        let bvh = Bvh::build(&[], &[], &[]);
+        assert_eq!((bvh.nodes.len(), bvh.prims.len()), (1, 0));
+        assert_eq!(bvh.intersect(&[], &[], Vec3::zero(), Vec3::unit_z(), 0.001, 1000.0), None);
+    }
+
+    #[test]
+    fn spheres_match_brute_force() {
+        let spheres = spheres();
+        check(&Bvh::build(&spheres, &[], &[]), &spheres, &[], 1000);
+    }
+
+    #[test]
+    fn tris_match_brute_force() {
+        let tris = tris(1, 2000);
+        check(&Bvh::build(&[], &tris, &[]), &[], &tris, 1000);
+    }
+
+    #[test]
+    fn grafted_subtrees_match_brute_force() {
+        // two meshes built on their own and then moved, like cached meshes, next to loose triangles and spheres
+        let (mesh, loose) = (tris(2, 500), tris(3, 200));
+        let moved = |offset: Vec3| mesh.iter().map(move |t| Tri::new(t.a + offset, t.b + offset, t.c + offset, t.an, t.bn, t.cn));
+        let tris: Vec<Tri> = moved(Vec3::new(5.0, 0.0, 0.0)).chain(loose).chain(moved(Vec3::new(0.0, -10.0, 3.0))).collect();
+        let subtrees = [(0, Bvh::build(&[], &mesh, &[])), (700, Bvh::build(&[], &mesh, &[]))];
+        let spheres = spheres();
+        check(&Bvh::build(&spheres, &tris, &subtrees), &spheres, &tris, 1000);
+    }
+}
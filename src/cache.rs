@@ -0,0 +1,241 @@
+use std::{fs::{metadata, read, write}, mem::{size_of, size_of_val}, time::UNIX_EPOCH};
+
+use crate::{bvh::{Bvh, Node, TRI_BIT}, material::{Material, TexturedMaterial}, mesh::LoadedMesh, obj::{Group, Obj, Tri}, scene::Camera};
+
+const MAGIC: &[u8; 4] = b"RTMC";
+/// bump whenever the layout below or `Tri`/`Node` change
+const VERSION: u32 = 6;
+
+/// the cache for `path` is written next to it
+pub fn cache_path(path: &str) -> String {
+    format!("{path}.rtcache")
+}
+
+/// 64 bit FNV-1a
+fn hash(data: &[u8]) -> u64 {
+    data.iter().fold(0xcbf29ce484222325, |h, &x| (h ^ x as u64).wrapping_mul(0x100000001b3))
+}
+
+fn mtime(path: &str) -> std::io::Result<u64> {
+    let time = metadata(path)?.modified()?;
+    Ok(time.duration_since(UNIX_EPOCH).map_or(0, |x| x.as_nanos() as u64))
+}
+
+/// the model space mesh cached for `path`, if the source still has the same mtime and contents and was loaded with the
+/// same `crease_angle`. only the source file itself is checked, not mtl libraries or gltf buffers it references
+pub fn read_cache(path: &str, crease_angle: Option<f32>) -> Option<LoadedMesh> {
+    let data = read(cache_path(path)).ok()?;
+    let mut r = Reader(&data);
+    if r.bytes(4)? != MAGIC || r.u32()? != VERSION || r.u64()? != mtime(path).ok()? || r.u32()? != crease_angle.map_or(u32::MAX, f32::to_bits) {
+        return None;
+    }
+    // only hash the source once the cheap checks pass
+    if r.u64()? != hash(&read(path).ok()?) {
+        return None;
+    }
+
+    let camera = match r.u32()? {
+        0 => None,
+        _ => {
+            let v: Vec<f32> = r.array(10)?;
+            Some(Camera { lookfrom: [v[0], v[1], v[2]], lookat: [v[3], v[4], v[5]], vfov: v[6], vup: [v[7], v[8], v[9]] })
+        },
+    };
+    let mut materials = vec![];
+    for _ in 0..r.u64()? {
+        let material = Material::decode(r.array::<[[f32; 4]; 4]>(1)?[0])?;
+        let albedo_map = match r.u32()? {
+            0 => None,
+            _ => Some(r.string()?),
+        };
+        materials.push(TexturedMaterial { material, albedo_map });
+    }
+    let mut groups = vec![];
+    for _ in 0..r.u64()? {
+        let name = r.string()?;
+        groups.push(Group { name, tris: r.u64()? as usize..r.u64()? as usize });
+    }
+    let len = r.u64()? as usize;
+    let tris = r.array::<Tri>(len)?;
+    let len = r.u64()? as usize;
+    let nodes = r.array::<Node>(len)?;
+    let len = r.u64()? as usize;
+    let prims = r.array::<u32>(len)?;
+    let bad_node = |x: &Node| if x.count == 0 { x.index as usize >= nodes.len() } else { (x.index as usize).checked_add(x.count as usize).is_none_or(|end| end > prims.len()) };
+    let bad_prim = |x: &u32| (x & !TRI_BIT) as usize >= tris.len();
+    let bad_tri = |x: &Tri| x.mat as usize >= materials.len();
+    if !r.0.is_empty() || groups.iter().any(|x| x.tris.start > x.tris.end || x.tris.end > tris.len()) || tris.iter().any(bad_tri) || nodes.iter().any(bad_node) || prims.iter().any(bad_prim) {
+        return None;
+    }
+
+    let obj = Obj { tris, materials, groups, pos: Default::default(), rot: Default::default(), scale: 1.0 };
+    Some(LoadedMesh { obj, camera, bvh: Bvh { nodes, prims } })
+}
+
+/// `mesh` has to be in model space
+pub fn write_cache(path: &str, crease_angle: Option<f32>, mesh: &LoadedMesh) -> std::io::Result<()> {
+    let mut w = MAGIC.to_vec();
+    w.extend(VERSION.to_le_bytes());
+    w.extend(mtime(path)?.to_le_bytes());
+    w.extend(crease_angle.map_or(u32::MAX, f32::to_bits).to_le_bytes());
+    w.extend(hash(&read(path)?).to_le_bytes());
+
+    match &mesh.camera {
+        Some(x) => {
+            w.extend(1u32.to_le_bytes());
+            w.extend(as_bytes(&[x.lookfrom[0], x.lookfrom[1], x.lookfrom[2], x.lookat[0], x.lookat[1], x.lookat[2], x.vfov, x.vup[0], x.vup[1], x.vup[2]]));
+        },
+        None => w.extend(0u32.to_le_bytes()),
+    }
+    w.extend((mesh.obj.materials.len() as u64).to_le_bytes());
+    for material in &mesh.obj.materials {
+        w.extend(as_bytes(&material.material.encode()));
+        match &material.albedo_map {
+            Some(x) => {
+                w.extend(1u32.to_le_bytes());
+                write_string(&mut w, x);
+            },
+            None => w.extend(0u32.to_le_bytes()),
+        }
+    }
+    w.extend((mesh.obj.groups.len() as u64).to_le_bytes());
+    for group in &mesh.obj.groups {
+        write_string(&mut w, &group.name);
+        w.extend((group.tris.start as u64).to_le_bytes());
+        w.extend((group.tris.end as u64).to_le_bytes());
+    }
+    w.extend((mesh.obj.tris.len() as u64).to_le_bytes());
+    w.extend(as_bytes(&mesh.obj.tris));
+    w.extend((mesh.bvh.nodes.len() as u64).to_le_bytes());
+    w.extend(as_bytes(&mesh.bvh.nodes));
+    w.extend((mesh.bvh.prims.len() as u64).to_le_bytes());
+    w.extend(as_bytes(&mesh.bvh.prims));
+    write(cache_path(path), w)
+}
+
+fn write_string(w: &mut Vec<u8>, x: &str) {
+    w.extend((x.len() as u64).to_le_bytes());
+    w.extend(x.as_bytes());
+}
+
+/// only for the plain old data written to the cache, which is read back on the same machine so the byte order matches
+fn as_bytes<T: Copy>(data: &[T]) -> &[u8] {
+    unsafe { std::slice::from_raw_parts(data.as_ptr() as *const u8, size_of_val(data)) }
+}
+
+struct Reader<'a>(&'a [u8]);
+impl<'a> Reader<'a> {
+    fn bytes(&mut self, len: usize) -> Option<&'a [u8]> {
+        if self.0.len() < len {
+            return None;
+        }
+        let (x, rest) = self.0.split_at(len);
+        self.0 = rest;
+        Some(x)
+    }
+    fn u32(&mut self) -> Option<u32> {
+        Some(u32::from_le_bytes(self.bytes(4)?.try_into().unwrap()))
+    }
+    fn u64(&mut self) -> Option<u64> {
+        Some(u64::from_le_bytes(self.bytes(8)?.try_into().unwrap()))
+    }
+    fn string(&mut self) -> Option<String> {
+        let len = self.u64()? as usize;
+        String::from_utf8(self.bytes(len)?.to_vec()).ok()
+    }
+    /// `T` has to be plain old data that every bit pattern is valid for
+    fn array<T: Copy>(&mut self, len: usize) -> Option<Vec<T>> {
+        let bytes = self.bytes(size_of::<T>().checked_mul(len)?)?;
+        let mut out = Vec::<T>::with_capacity(len);
+        unsafe {
+            std::ptr::copy_nonoverlapping(bytes.as_ptr(), out.as_mut_ptr() as *mut u8, bytes.len());
+            out.set_len(len);
+        }
+        Some(out)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{env::temp_dir, fs::remove_file};
+
+    use vek::Vec3;
+
+    use super::*;
+    use crate::mesh::load_mesh;
+
+    /// a quad written to its own file per test, since the tests run in parallel, and cached by loading it
+    fn cached(name: &str) -> String {
+        let path = temp_dir().join(format!("rtcache_{name}.obj")).to_string_lossy().into_owned();
+        write(&path, "v 0 0 0\nv 1 0 0\nv 1 1 0\nv 0 1 0\nf 1 2 3 4\n").unwrap();
+        load_mesh(&path, Vec3::zero(), Vec3::zero(), 1.0, None, true).unwrap();
+        path
+    }
+
+    fn clean(path: &str) {
+        remove_file(path).unwrap();
+        remove_file(cache_path(path)).unwrap();
+    }
+
+    #[test]
+    fn corrupt_material_index() {
+        let path = cached("corrupt");
+        let mut data = read(cache_path(&path)).unwrap();
+        // the first triangle's material, found from the end since only the arrays after it have a fixed size
+        let mesh = read_cache(&path, None).unwrap();
+        let tris = data.len() - (8 + mesh.bvh.prims.len() * 4) - (8 + mesh.bvh.nodes.len() * size_of::<Node>()) - mesh.obj.tris.len() * size_of::<Tri>();
+        data[tris + 12] = 1;
+        write(cache_path(&path), data).unwrap();
+        assert!(read_cache(&path, None).is_none());
+        clean(&path);
+    }
+
+    #[test]
+    fn reused_while_unchanged() {
+        let path = cached("reused");
+        let mesh = read_cache(&path, None).unwrap();
+        assert_eq!((mesh.obj.tris.len(), mesh.obj.materials.len(), mesh.obj.groups.len()), (2, 1, 1));
+        assert_eq!(mesh.obj.tris[1].c, Vec3::new(0.0, 1.0, 0.0));
+        assert!(!mesh.bvh.nodes.is_empty() && mesh.bvh.prims.len() == 2);
+        clean(&path);
+    }
+
+    #[test]
+    fn rejected_after_content_change() {
+        let path = cached("content");
+        // keep the mtime so only the hash can tell
+        let time = metadata(&path).unwrap().modified().unwrap();
+        write(&path, "v 0 0 0\nv 2 0 0\nv 1 1 0\nv 0 1 0\nf 1 2 3 4\n").unwrap();
+        std::fs::File::options().write(true).open(&path).unwrap().set_modified(time).unwrap();
+        assert!(read_cache(&path, None).is_none());
+        clean(&path);
+    }
+
+    #[test]
+    fn rejected_after_crease_angle_change() {
+        let path = cached("crease");
+        assert!(read_cache(&path, Some(0.5)).is_none());
+        clean(&path);
+    }
+
+    #[test]
+    fn rejected_after_version_bump() {
+        let path = cached("version");
+        let mut data = read(cache_path(&path)).unwrap();
+        data[4..8].copy_from_slice(&(VERSION + 1).to_le_bytes());
+        write(cache_path(&path), data).unwrap();
+        assert!(read_cache(&path, None).is_none());
+        clean(&path);
+    }
+
+    #[test]
+    fn truncated() {
+        let path = cached("truncated");
+        let data = read(cache_path(&path)).unwrap();
+        for len in 0..data.len() {
+            write(cache_path(&path), &data[..len]).unwrap();
+            assert!(read_cache(&path, None).is_none(), "a cache cut to {len} bytes was read");
+        }
+        clean(&path);
+    }
+}
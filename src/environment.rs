@@ -0,0 +1,15 @@
+/// an equirectangular environment map, the center of the image is in the -z direction and the top row is straight up
+pub struct EnvMap {
+    pub width: u32,
+    pub height: u32,
+    /// linear rgb and an unused 0, rows from the top
+    pub texels: Vec<[f32; 4]>,
+}
+impl EnvMap {
+    /// anything the image crate reads, meant for .hdr and .exr files
+    pub fn load(path: &str) -> Result<Self, image::ImageError> {
+        let image = image::open(path)?.into_rgb32f();
+        let texels = image.pixels().map(|x| [x.0[0], x.0[1], x.0[2], 0.0]).collect();
+        Ok(Self { width: image.width(), height: image.height(), texels })
+    }
+}
@@ -0,0 +1,169 @@
+use std::{collections::HashMap, fmt::Display, path::Path};
+
+use ::gltf::{camera::Projection, mesh::Mode, Gltf, Node};
+use vek::{Mat4 as TMat4, Vec2 as TVec2, Vec3 as TVec3};
+
+use crate::{material::{Material, Principled, TexturedMaterial}, mesh::IndexedMesh, mtl::MtlMaterial, obj::{Group, Obj, Tri}, scene::Camera};
+
+type Vec2 = TVec2<f32>;
+type Vec3 = TVec3<f32>;
+type Mat4 = TMat4<f32>;
+
+/// loads every mesh in the default scene of a .gltf or .glb file, placed by the node hierarchy and then by `pos`, `rot`
+/// and `scale` like `Obj::load_from_file`. every node with a mesh is a group named by its path, `"parent/child"`
+///
+/// also returns the first perspective camera in the scene, focused on the center of the meshes
+pub fn load_gltf(path: &str, pos: Vec3, rot: Vec3, scale: f32, crease_angle: Option<f32>) -> Result<(Obj, Option<Camera>), GltfError> {
+    let err = |reason| GltfError { path: path.to_string(), reason };
+    let Gltf { document, blob } = Gltf::open(path).map_err(|x| err(GltfErrorReason::Gltf(x)))?;
+    let buffers = ::gltf::import_buffers(&document, Path::new(path).parent(), blob).map_err(|x| err(GltfErrorReason::Gltf(x)))?;
+
+    let mut materials = vec![];
+    let mut material_ids = HashMap::new();
+    let mut groups = vec![];
+    let mut group_ids = HashMap::new();
+    let mut grouped: Vec<(usize, Tri)> = vec![];
+    let mut camera = None;
+
+    let roots: Vec<Node> = document.default_scene().or_else(|| document.scenes().next()).map_or(vec![], |x| x.nodes().collect());
+    let mut stack: Vec<(Node, Mat4, String)> = roots.into_iter().rev().map(|x| (x, Mat4::identity(), String::new())).collect();
+    while let Some((node, parent, prefix)) = stack.pop() {
+        let world = parent * Mat4::from_col_arrays(node.transform().matrix());
+        let name = node.name().map_or_else(|| node.index().to_string(), str::to_string);
+        let name = if prefix.is_empty() { name } else { format!("{prefix}/{name}") };
+        if let (None, Some(x)) = (camera, node.camera()) {
+            if let Projection::Perspective(x) = x.projection() {
+                camera = Some((world, x.yfov()));
+            }
+        }
+        if let Some(mesh) = node.mesh() {
+            let group = *group_ids.entry(name.clone()).or_insert_with(|| {
+                groups.push(name.clone());
+                groups.len() - 1
+            });
+            let normal = world.inverted().transposed();
+            for primitive in mesh.primitives() {
+                let reader = primitive.reader(|x| Some(&buffers[x.index()]));
+                let Some(positions) = reader.read_positions() else {
+                    continue;
+                };
+                let positions: Vec<Vec3> = positions.map(Vec3::from).collect();
+                let indices: Vec<usize> = reader.read_indices().map_or_else(|| (0..positions.len()).collect(), |x| x.into_u32().map(|x| x as usize).collect());
+                if let Some(&i) = indices.iter().find(|&&i| i >= positions.len()) {
+                    return Err(err(GltfErrorReason::IndexOutOfRange(i, positions.len())));
+                }
+                let faces: Vec<[usize; 3]> = match primitive.mode() {
+                    Mode::Triangles => indices.chunks_exact(3).map(|x| [x[0], x[1], x[2]]).collect(),
+                    // every other triangle of a strip is flipped to keep the winding consistent
+                    Mode::TriangleStrip => (2..indices.len()).map(|i| if i % 2 == 0 { [indices[i - 2], indices[i - 1], indices[i]] } else { [indices[i - 1], indices[i - 2], indices[i]] }).collect(),
+                    Mode::TriangleFan => (2..indices.len()).map(|i| [indices[0], indices[i - 1], indices[i]]).collect(),
+                    mode => {
+                        eprintln!("warning: {path}: skipping {mode:?} primitive of {name:?}");
+                        continue;
+                    },
+                };
+
+                let normals: Option<Vec<Vec3>> = reader.read_normals().map(|x| x.map(Vec3::from).collect());
+                if normals.as_ref().is_some_and(|x| x.len() != positions.len()) {
+                    return Err(err(GltfErrorReason::AttributeCount("NORMAL")));
+                }
+                // gltf puts the texture origin in the top left, obj in the bottom left
+                let texcoords: Option<Vec<Vec2>> = reader.read_tex_coords(0).map(|x| x.into_f32().map(|[u, v]| Vec2::new(u, 1.0 - v)).collect());
+                if texcoords.as_ref().is_some_and(|x| x.len() != positions.len()) {
+                    return Err(err(GltfErrorReason::AttributeCount("TEXCOORD_0")));
+                }
+
+                let material = primitive.material();
+                let mat = *material_ids.entry(material.index()).or_insert_with(|| {
+                    materials.push(TexturedMaterial { material: to_material(&material), albedo_map: base_color_map(path, &material) });
+                    materials.len() - 1
+                });
+                let indexed = IndexedMesh { positions, faces, normals, texcoords };
+                grouped.extend(indexed.tris(crease_angle).into_iter().map(|mut t| {
+                    t.mat = mat as u32;
+                    (group, t.transformed(world, normal))
+                }));
+            }
+        }
+        stack.extend(node.children().map(|x| (x, world, name.clone())));
+    }
+
+    grouped.sort_by_key(|x| x.0);
+    let groups = groups.into_iter().enumerate().map(|(i, name)| {
+        let start = grouped.partition_point(|x| x.0 < i);
+        let end = grouped.partition_point(|x| x.0 <= i);
+        Group { name, tris: start..end }
+    }).collect();
+    let tris = grouped.into_iter().map(|x| x.1).collect();
+    let mut obj = Obj { tris, materials, groups, pos, rot, scale };
+    obj.apply_transform();
+
+    let camera = camera.map(|(view, yfov)| {
+        let view = obj.get_model_world_matrix() * view;
+        let lookfrom = view.mul_point(Vec3::zero());
+        let forward = view.mul_direction(-Vec3::unit_z()).normalized();
+        // focus on the middle of the model, or just in front of the camera if it is behind
+        let (min, max) = obj.tris.iter().flat_map(|x| [x.a, x.b, x.c]).fold((Vec3::broadcast(f32::INFINITY), Vec3::broadcast(f32::NEG_INFINITY)), |(min, max), x| (Vec3::partial_min(min, x), Vec3::partial_max(max, x)));
+        let focus = ((min + max) / 2.0 - lookfrom).dot(forward);
+        let focus = if focus.is_finite() && focus > 0.0 { focus } else { 1.0 };
+        let vup = view.mul_direction(Vec3::unit_y()).normalized();
+        Camera { lookfrom: lookfrom.into_array(), lookat: (lookfrom + forward * focus).into_array(), vfov: yfov.to_degrees(), vup: vup.into_array() }
+    });
+    Ok((obj, camera))
+}
+
+/// the metallic roughness model with its emission, ior and transmission extensions maps straight onto the principled
+/// material
+fn to_material(material: &::gltf::Material) -> Material {
+    // primitives without a material get the same default as obj faces without `usemtl`
+    if material.index().is_none() {
+        return MtlMaterial::default().to_material();
+    }
+    let pbr = material.pbr_metallic_roughness();
+    let [r, g, b, _] = pbr.base_color_factor();
+    Material::Principled(Principled {
+        base_color: [r, g, b],
+        metallic: pbr.metallic_factor(),
+        roughness: pbr.roughness_factor(),
+        transmission: material.transmission().map_or(0.0, |x| x.transmission_factor()),
+        ior: material.ior().unwrap_or(1.5),
+        emission: material.emissive_factor().map(|x| x * material.emissive_strength().unwrap_or(1.0)),
+        ..Default::default()
+    })
+}
+
+/// the base color texture if it is a separate file, relative to the working directory
+fn base_color_map(path: &str, material: &::gltf::Material) -> Option<String> {
+    let texture = material.pbr_metallic_roughness().base_color_texture()?.texture();
+    match texture.source().source() {
+        ::gltf::image::Source::Uri { uri, .. } if !uri.starts_with("data:") => Some(Path::new(path).parent().unwrap_or(Path::new("")).join(uri).to_string_lossy().into_owned()),
+        _ => {
+            eprintln!("warning: {path}: ignoring the embedded base color texture of material {:?}", material.name().unwrap_or(""));
+            None
+        },
+    }
+}
+
+#[derive(Debug)]
+pub struct GltfError {
+    pub path: String,
+    pub reason: GltfErrorReason,
+}
+#[derive(Debug)]
+pub enum GltfErrorReason {
+    Gltf(::gltf::Error),
+    /// index and vertex count
+    IndexOutOfRange(usize, usize),
+    /// an attribute with a different number of elements than `POSITION`
+    AttributeCount(&'static str),
+}
+impl Display for GltfError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}: ", self.path)?;
+        match &self.reason {
+            GltfErrorReason::Gltf(err) => write!(f, "{err}"),
+            GltfErrorReason::IndexOutOfRange(i, count) => write!(f, "index {i} out of range for {count} vertices"),
+            GltfErrorReason::AttributeCount(name) => write!(f, "{name} does not have one value per vertex"),
+        }
+    }
+}
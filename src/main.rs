@@ -1,4 +1,4 @@
-use std::ptr::slice_from_raw_parts;
+use std::{mem::size_of, ptr::slice_from_raw_parts};
 
 use ash::vk::{BufferCopy, CommandBufferBeginInfo, CommandBufferResetFlags, FenceCreateInfo, PipelineBindPoint, StructureType, SubmitInfo};
 use image::Rgba;
@@ -6,42 +6,133 @@ use rand::RngCore;
 use vulkan::device::buffer::{StagedSSBO, StagedUBO};
 
 pub mod vulkan;
+mod bvh;
+mod cache;
+mod environment;
+mod gltf;
+mod material;
+mod mesh;
+mod mtl;
 mod obj;
+mod ply;
+#[cfg(test)]
+mod sampling;
+mod scene;
+mod sky;
+mod stl;
+mod texture;
 
-const WIDTH: usize = 1920;
-const HEIGHT: usize = 1200;
-
+#[derive(Default)]
 #[repr(C)]
 struct UBOData {
     size: [u32; 4],
-    cam: [f32; 8],
-    spheres: Vec<f32>,
+    cam: [f32; 7],
+    tri_count: u32,
+    /// 0 without an environment map
+    env_size: [u32; 2],
+    /// rotation in radians, intensity
+    env: [f32; 2],
+    /// of the sky distribution, 0 to not sample the sky
+    sky_size: [u32; 2],
+    /// 0 for the gradient, 1 for preetham, then padding
+    sky_model: [u32; 2],
+    /// direction and the cosine of the angular radius
+    sun: [f32; 4],
+    /// the w components are padding from here
+    sun_radiance: [f32; 4],
+    zenith: [f32; 4],
+    horizon: [f32; 4],
+    perez: [[f32; 4]; 5],
+    zenith_yxy: [f32; 3],
+    light_count: u32,
+    /// min and max bounces, then padding
+    depth: [u32; 4],
+    /// the w component is padding
+    vup: [f32; 4],
 }
-impl<'a> UBOData {
-    fn new(size: [usize; 2], spheres: Vec<f32>, count: usize, seed: u32, vfov: f32, lookfrom: [f32; 3], lookat: [f32; 3]) -> Self {
-        Self { size: [size[0] as u32, size[1] as u32, count as u32, seed], spheres, cam: [lookfrom[0], lookfrom[1], lookfrom[2], vfov.to_radians(), lookat[0], lookat[1], lookat[2], 0.0]}
+impl UBOData {
+    fn new(size: [usize; 2], count: usize, tri_count: usize, seed: u32, camera: &scene::Camera) -> Self {
+        let scene::Camera { lookfrom, lookat, vfov, vup } = *camera;
+        Self {
+            size: [size[0] as u32, size[1] as u32, count as u32, seed],
+            cam: [lookfrom[0], lookfrom[1], lookfrom[2], vfov.to_radians(), lookat[0], lookat[1], lookat[2]],
+            tri_count: tri_count as u32,
+            vup: [vup[0], vup[1], vup[2], 0.0],
+            ..Default::default()
+        }
+    }
+    fn set_sky(&mut self, sky: &sky::ProceduralSky) {
+        let w = |x: vek::Vec3<f32>| [x.x, x.y, x.z, 0.0];
+        self.sky_model = [sky.model as u32, 0];
+        self.sun = [sky.sun_dir.x, sky.sun_dir.y, sky.sun_dir.z, sky.sun_cos];
+        (self.sun_radiance, self.zenith, self.horizon) = (w(sky.sun_radiance), w(sky.zenith), w(sky.horizon));
+        self.perez = sky.perez.map(w);
+        self.zenith_yxy = sky.zenith_yxy.into_array();
     }
     fn vec(&self) -> Vec<u8> {
-        let mut vec = unsafe { &*slice_from_raw_parts(self as *const Self as *const u8, 16+32) }.to_vec();
-        vec.extend_from_slice(unsafe { &*slice_from_raw_parts(self.spheres.as_ptr() as *const u8, 4*self.spheres.len()) });
-        vec
+        unsafe { &*slice_from_raw_parts(self as *const Self as *const u8, size_of::<Self>()) }.to_vec()
     }
 }
 
+fn usage(err: &str) -> ! {
+    eprintln!("{err}\nusage: gpu-raytrace [scene.toml] [--min-depth n] [--max-depth n]");
+    std::process::exit(2)
+}
+
 fn main() {
+    let mut path = "scene.toml".to_string();
+    let (mut min_depth, mut max_depth) = (None, None);
+    let mut args = std::env::args().skip(1);
+    while let Some(arg) = args.next() {
+        let mut value = || args.next().and_then(|x| x.parse::<u32>().ok()).unwrap_or_else(|| usage(&format!("{arg} needs a number")));
+        match arg.as_str() {
+            "--min-depth" => min_depth = Some(value()),
+            "--max-depth" => max_depth = Some(value()),
+            // a misspelled option would otherwise be taken as the scene
+            x if x.starts_with("--") => usage(&format!("unknown option {x}")),
+            _ => path = arg,
+        }
+    }
+
     let vk = vulkan::VulkanHandle::new().unwrap();
     let physical = vulkan::device::PhysicalDevice::find_device(&vk).unwrap().unwrap();
     let logical = vulkan::device::LogicalDevice::create_logical_device(physical);
     let queue = logical.create_queue();
 
-    let mut dat = vec![0.0, 0.0, -1.0, 0.5, 0.0, -1000.5, -1.0, 1000.0, -1.0, 0.0, -1.0, 0.5, 1.0, 0.0, -1.0, 0.5];
-    dat.resize(64*4, 0.0);
-    dat.extend_from_slice(&[0.1, 0.2, 0.5, f32::INFINITY, 0.8, 0.8, 0.0, f32::INFINITY, 1.5, f32::INFINITY, f32::INFINITY, f32::INFINITY, 1.0, 1.0, 1.0, 0.0]);
-    let mut ubodata = UBOData::new([WIDTH, HEIGHT], dat, 4, rand::thread_rng().next_u32(), 30.0, [-3.0, 2.0, 1.0], [0.0, 0.0, -1.0]);
+    let mut scene = scene::Scene::load(&path).map_err(|x| x.to_string()).unwrap();
+    scene.render.min_depth = min_depth.unwrap_or(scene.render.min_depth);
+    scene.render.max_depth = max_depth.unwrap_or(scene.render.max_depth);
+    let (width, height) = (scene.render.width, scene.render.height);
+
+    let scene::SceneData { camera, spheres, materials, textures, environment, tris, subtrees, lights } = scene.build().map_err(|x| x.to_string()).unwrap();
+    let bvh = bvh::Bvh::build(&spheres, &tris, &subtrees);
+    let mut ubodata = UBOData::new([width, height], scene.spheres.len(), tris.len(), rand::thread_rng().next_u32(), &camera);
+
+    if let (Some(map), Some(env)) = (&environment, &scene.environment) {
+        ubodata.env_size = [map.width, map.height];
+        ubodata.env = [env.rotation.to_radians(), env.intensity];
+    }
+
+    let procedural = sky::ProceduralSky::new(&scene.sky);
+    ubodata.set_sky(&procedural);
+    let sky = sky::SkyDistribution::build(environment.as_ref(), &procedural);
+    ubodata.sky_size = sky.as_ref().map_or([0; 2], |x| [x.width, x.height]);
+    ubodata.light_count = lights.len() as u32;
+    ubodata.depth = [scene.render.min_depth, scene.render.max_depth, 0, 0];
 
     let mut ubo = StagedUBO::new(&logical, ubodata.vec());
-    let mut ssbo = StagedSSBO::<f32>::new(&logical, WIDTH * HEIGHT * 4);
-    let pipe = vulkan::device::shaders::Pipeline::new("shader.spv", &ubo, &ssbo, &logical);
+    let mut ssbo = StagedSSBO::<f32>::new(&logical, width * height * 4);
+    let spheres = StagedSSBO::from_vec(&logical, spheres);
+    let materials = StagedSSBO::from_vec(&logical, materials);
+    let tris = StagedSSBO::from_vec(&logical, tris);
+    let nodes = StagedSSBO::from_vec(&logical, bvh.nodes);
+    let prims = StagedSSBO::from_vec(&logical, bvh.prims);
+    let texture_headers = StagedSSBO::from_vec(&logical, textures.headers);
+    let texels = StagedSSBO::from_vec(&logical, textures.texels);
+    let env_texels = StagedSSBO::from_vec(&logical, environment.map_or(vec![], |x| x.texels));
+    let sky_cdf = StagedSSBO::from_vec(&logical, sky.map_or(vec![], |x| x.cdf));
+    let lights = StagedSSBO::from_vec(&logical, lights);
+    let pipe = vulkan::device::shaders::Pipeline::new("shader.spv", &ubo, &[ssbo.descriptor(), spheres.descriptor(), materials.descriptor(), tris.descriptor(), nodes.descriptor(), prims.descriptor(), texture_headers.descriptor(), texels.descriptor(), env_texels.descriptor(), sky_cdf.descriptor(), lights.descriptor()], &logical);
     let cmd = logical.create_command_buffer();
     let info = CommandBufferBeginInfo {
         s_type: StructureType::COMMAND_BUFFER_BEGIN_INFO,
@@ -52,8 +143,30 @@ fn main() {
         ..Default::default()
     };
     let fence = unsafe { logical.device.create_fence(&fence, None) }.unwrap();
+
+    unsafe { logical.device.begin_command_buffer(cmd, &info) }.unwrap();
+    spheres.record_upload(cmd);
+    materials.record_upload(cmd);
+    tris.record_upload(cmd);
+    nodes.record_upload(cmd);
+    prims.record_upload(cmd);
+    texture_headers.record_upload(cmd);
+    texels.record_upload(cmd);
+    env_texels.record_upload(cmd);
+    sky_cdf.record_upload(cmd);
+    lights.record_upload(cmd);
+    unsafe { logical.device.end_command_buffer(cmd) }.unwrap();
+    unsafe { logical.device.queue_submit(queue.queue, &[SubmitInfo{
+        s_type: StructureType::SUBMIT_INFO,
+        command_buffer_count: 1,
+        p_command_buffers: &cmd,
+        ..Default::default()
+    }], fence) }.unwrap();
+    unsafe { logical.device.wait_for_fences(&[fence], true, u64::MAX) }.unwrap();
+    unsafe { logical.device.reset_fences(&[fence]) }.unwrap();
+    unsafe { logical.device.reset_command_buffer(cmd, CommandBufferResetFlags::default()) }.unwrap();
     
-    let samples = 100;
+    let samples = scene.render.samples;
     for x in 0usize..samples {
         ubodata.size[3] = rand::thread_rng().next_u32();
         ubo.get_slice().copy_from_slice(&ubodata.vec());
@@ -75,7 +188,7 @@ fn main() {
         unsafe { logical.device.begin_command_buffer(cmd, &info) }.unwrap();
         unsafe { logical.device.cmd_bind_pipeline(cmd, PipelineBindPoint::COMPUTE, pipe.pipeline) };
         unsafe { logical.device.cmd_bind_descriptor_sets(cmd, PipelineBindPoint::COMPUTE, pipe.layout, 0, &[pipe.descriptor_set], &[]) };
-        unsafe { logical.device.cmd_dispatch(cmd, (WIDTH as f32/32.0).ceil() as u32, (HEIGHT as f32/32.0).ceil() as u32, 1) };
+        unsafe { logical.device.cmd_dispatch(cmd, (width as f32/32.0).ceil() as u32, (height as f32/32.0).ceil() as u32, 1) };
         unsafe { logical.device.end_command_buffer(cmd) }.unwrap();
         unsafe { logical.device.queue_submit(queue.queue, &[SubmitInfo{
             s_type: StructureType::SUBMIT_INFO,
@@ -107,7 +220,7 @@ fn main() {
     }
     println!("{:?}", &ssbo.get_slice()[0..20]);
     let image = Vec::from_iter(ssbo.get_slice().iter().map(|x| (*x*255.0/samples as f32) as u8));
-    image::ImageBuffer::<Rgba<u8>, _>::from_vec(WIDTH as u32, HEIGHT as u32, image).unwrap().save(format!("img.png")).map_err(|x| x.to_string()).unwrap();
+    image::ImageBuffer::<Rgba<u8>, _>::from_vec(width as u32, height as u32, image).unwrap().save(&scene.render.output).map_err(|x| x.to_string()).unwrap();
 
     unsafe { logical.device.destroy_fence(fence, None) };
 }
\ No newline at end of file
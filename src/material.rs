@@ -0,0 +1,258 @@
+use serde::Deserialize;
+
+/// material kinds understood by shader.comp, see `encode` for the vec4 layout
+#[derive(Deserialize, Clone, Copy, PartialEq, Debug)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum Material {
+    Lambertian { albedo: [f32; 3] },
+    Metal { albedo: [f32; 3], fuzz: f32 },
+    Dielectric {
+        ior: f32,
+        /// the color white light fades to after `absorption_distance` inside, clear by default
+        #[serde(default = "white")]
+        absorption: [f32; 3],
+        #[serde(default = "one")]
+        absorption_distance: f32,
+    },
+    Emissive { color: [f32; 3] },
+    /// GGX microfacet metal, `albedo` is the reflectance at normal incidence
+    GgxMetal {
+        albedo: [f32; 3],
+        roughness: f32,
+        /// from -1 to 1, stretching the highlight along the texture u direction or across it. spheres go around the y
+        /// axis and triangles without texture coordinates along their first edge
+        #[serde(default)]
+        anisotropy: f32,
+    },
+    /// GGX microfacet glass that both reflects and refracts
+    RoughDielectric {
+        ior: f32,
+        roughness: f32,
+        #[serde(default)]
+        anisotropy: f32,
+        /// see `Dielectric`
+        #[serde(default = "white")]
+        absorption: [f32; 3],
+        #[serde(default = "one")]
+        absorption_distance: f32,
+    },
+    Principled(Principled),
+}
+
+fn white() -> [f32; 3] {
+    [1.0; 3]
+}
+fn one() -> f32 {
+    1.0
+}
+
+/// a disney style uber material, everything but `ior` and `emission` goes from 0 to 1
+#[derive(Deserialize, Clone, Copy, PartialEq, Debug)]
+#[serde(default)]
+pub struct Principled {
+    pub base_color: [f32; 3],
+    pub metallic: f32,
+    pub roughness: f32,
+    /// reflectance of the dielectric part at normal incidence, 0.5 is 4%
+    pub specular: f32,
+    /// a second, colorless specular layer
+    pub clearcoat: f32,
+    pub clearcoat_roughness: f32,
+    /// extra grazing reflection for cloth
+    pub sheen: f32,
+    /// from a white sheen to one tinted by `base_color`
+    pub sheen_tint: f32,
+    /// how much of the dielectric part refracts like rough glass tinted by `base_color`
+    pub transmission: f32,
+    pub ior: f32,
+    pub emission: [f32; 3],
+}
+impl Default for Principled {
+    fn default() -> Self {
+        Self { base_color: [0.8; 3], metallic: 0.0, roughness: 0.5, specular: 0.5, clearcoat: 0.0, clearcoat_roughness: 0.03, sheen: 0.0, sheen_tint: 0.5, transmission: 0.0, ior: 1.5, emission: [0.0; 3] }
+    }
+}
+
+/// the `params.w` of kinds that don't fit the original `data` encoding
+pub const GGX_METAL: f32 = 1.0;
+pub const ROUGH_DIELECTRIC: f32 = 2.0;
+pub const PRINCIPLED: f32 = 3.0;
+
+impl Material {
+    /// `data`, `params` and `extra`, where `params` is 0 for the first four kinds and `extra` is 0 unless listed:
+    /// diffuse: vec4(col, inf)
+    /// metal: vec4(col, fuzz)
+    /// dielectric: vec4(ior, inf, inf, inf), with vec4(absorption, absorption_distance) in `extra[0]`
+    /// emissive: vec4(col, -inf)
+    /// ggx metal: vec4(col, roughness), vec4(anisotropy, 0, 0, GGX_METAL)
+    /// rough dielectric: vec4(ior, roughness, anisotropy, 0), vec4(0, 0, 0, ROUGH_DIELECTRIC), and `extra[0]` like
+    /// dielectrics
+    /// principled: vec4(base_color, metallic), vec4(roughness, specular, ior, PRINCIPLED),
+    /// vec4(clearcoat, clearcoat_roughness, sheen, sheen_tint), vec4(emission, transmission)
+    pub fn encode(&self) -> [[f32; 4]; 4] {
+        match *self {
+            Material::Lambertian { albedo: [r, g, b] } => [[r, g, b, f32::INFINITY], [0.0; 4], [0.0; 4], [0.0; 4]],
+            Material::Metal { albedo: [r, g, b], fuzz } => [[r, g, b, fuzz], [0.0; 4], [0.0; 4], [0.0; 4]],
+            Material::Dielectric { ior, absorption: [r, g, b], absorption_distance } => [[ior, f32::INFINITY, f32::INFINITY, f32::INFINITY], [0.0; 4], [r, g, b, absorption_distance], [0.0; 4]],
+            Material::Emissive { color: [r, g, b] } => [[r, g, b, f32::NEG_INFINITY], [0.0; 4], [0.0; 4], [0.0; 4]],
+            Material::GgxMetal { albedo: [r, g, b], roughness, anisotropy } => [[r, g, b, roughness], [anisotropy, 0.0, 0.0, GGX_METAL], [0.0; 4], [0.0; 4]],
+            Material::RoughDielectric { ior, roughness, anisotropy, absorption: [r, g, b], absorption_distance } => [[ior, roughness, anisotropy, 0.0], [0.0, 0.0, 0.0, ROUGH_DIELECTRIC], [r, g, b, absorption_distance], [0.0; 4]],
+            Material::Principled(x) => {
+                let ([r, g, b], [er, eg, eb]) = (x.base_color, x.emission);
+                [[r, g, b, x.metallic], [x.roughness, x.specular, x.ior, PRINCIPLED], [x.clearcoat, x.clearcoat_roughness, x.sheen, x.sheen_tint], [er, eg, eb, x.transmission]]
+            },
+        }
+    }
+    /// inverse of `encode`, checked in the same order as `ray_color`
+    pub fn decode(v: [[f32; 4]; 4]) -> Option<Self> {
+        let [[x, y, z, w], params, extra, emission] = v;
+        if params[3] == PRINCIPLED {
+            let [clearcoat, clearcoat_roughness, sheen, sheen_tint] = extra;
+            let [roughness, specular, ior, _] = params;
+            return Some(Material::Principled(Principled {
+                base_color: [x, y, z],
+                metallic: w,
+                roughness,
+                specular,
+                clearcoat,
+                clearcoat_roughness,
+                sheen,
+                sheen_tint,
+                transmission: emission[3],
+                ior,
+                emission: [emission[0], emission[1], emission[2]],
+            }));
+        }
+        let [r, g, b, absorption_distance] = extra;
+        if params[3] == ROUGH_DIELECTRIC && emission == [0.0; 4] {
+            Some(Material::RoughDielectric { ior: x, roughness: y, anisotropy: z, absorption: [r, g, b], absorption_distance })
+        } else if params[3] == GGX_METAL && extra == [0.0; 4] && emission == [0.0; 4] {
+            Some(Material::GgxMetal { albedo: [x, y, z], roughness: w, anisotropy: params[0] })
+        } else if params != [0.0; 4] || emission != [0.0; 4] {
+            None
+        } else if y.is_infinite() {
+            Some(Material::Dielectric { ior: x, absorption: [r, g, b], absorption_distance })
+        } else if extra != [0.0; 4] {
+            None
+        } else if w == f32::INFINITY {
+            Some(Material::Lambertian { albedo: [x, y, z] })
+        } else if w == f32::NEG_INFINITY {
+            Some(Material::Emissive { color: [x, y, z] })
+        } else if w.is_finite() {
+            Some(Material::Metal { albedo: [x, y, z], fuzz: w })
+        } else {
+            None
+        }
+    }
+    /// light given off by the surface, which makes it one of the lights that are sampled directly if it isn't black
+    pub fn emission(&self) -> [f32; 3] {
+        match *self {
+            Material::Emissive { color } => color,
+            Material::Principled(x) => x.emission,
+            _ => [0.0; 3],
+        }
+    }
+    /// a material is valid if the shader will read back exactly what was written and its parameters are in range
+    pub fn is_valid(&self) -> bool {
+        let unit = |x: f32| (0.0..=1.0).contains(&x);
+        let in_range = match *self {
+            Material::Dielectric { absorption, absorption_distance, .. } => absorption.into_iter().all(unit) && absorption_distance > 0.0,
+            Material::RoughDielectric { roughness, anisotropy, absorption, absorption_distance, .. } => unit(roughness) && (-1.0..=1.0).contains(&anisotropy) && absorption.into_iter().all(unit) && absorption_distance > 0.0,
+            Material::GgxMetal { roughness, anisotropy, .. } => unit(roughness) && (-1.0..=1.0).contains(&anisotropy),
+            Material::Principled(x) => [x.metallic, x.roughness, x.specular, x.clearcoat, x.clearcoat_roughness, x.sheen, x.sheen_tint, x.transmission].into_iter().all(unit) && x.ior > 0.0 && x.emission.iter().all(|&x| x >= 0.0),
+            _ => true,
+        };
+        in_range && Material::decode(self.encode()) == Some(*self)
+    }
+}
+
+/// a material and the images it is textured with, paths are relative to the working directory
+#[derive(Deserialize, Clone, PartialEq, Debug)]
+pub struct TexturedMaterial {
+    #[serde(flatten)]
+    pub material: Material,
+    /// multiplies the albedo, or the color of emissive materials
+    pub albedo_map: Option<String>,
+}
+impl From<Material> for TexturedMaterial {
+    fn from(material: Material) -> Self {
+        Self { material, albedo_map: None }
+    }
+}
+
+/// one entry of the material buffer
+#[derive(Clone, Copy)]
+#[repr(C)]
+pub struct GpuMaterial {
+    /// see `Material::encode`
+    pub data: [f32; 4],
+    pub params: [f32; 4],
+    pub extra: [[f32; 4]; 2],
+    /// texture indices or -1: albedo, then unused
+    pub maps: [i32; 4],
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const INF: f32 = f32::INFINITY;
+
+    #[test]
+    fn round_trip() {
+        let principled = Principled {
+            base_color: [0.1, 0.2, 0.3],
+            metallic: 0.4,
+            roughness: 0.5,
+            specular: 0.6,
+            clearcoat: 0.7,
+            clearcoat_roughness: 0.8,
+            sheen: 0.9,
+            sheen_tint: 0.15,
+            transmission: 0.25,
+            ior: 1.45,
+            emission: [2.0, 3.0, 4.0],
+        };
+        let materials = [
+            Material::Lambertian { albedo: [0.1, 0.2, 0.3] },
+            Material::Metal { albedo: [0.9, 0.8, 0.7], fuzz: 0.2 },
+            Material::Metal { albedo: [1.0; 3], fuzz: 0.0 },
+            Material::Dielectric { ior: 1.5, absorption: [1.0; 3], absorption_distance: 1.0 },
+            Material::Dielectric { ior: 1.33, absorption: [0.8, 0.9, 0.5], absorption_distance: 0.25 },
+            Material::Emissive { color: [4.0, 3.0, 2.0] },
+            Material::GgxMetal { albedo: [0.95, 0.64, 0.54], roughness: 0.3, anisotropy: -0.5 },
+            Material::GgxMetal { albedo: [0.5; 3], roughness: 0.0, anisotropy: 0.0 },
+            Material::RoughDielectric { ior: 1.5, roughness: 0.2, anisotropy: 0.0, absorption: [1.0; 3], absorption_distance: 1.0 },
+            Material::RoughDielectric { ior: 2.4, roughness: 0.7, anisotropy: 0.9, absorption: [0.2, 0.4, 0.6], absorption_distance: 3.0 },
+            Material::Principled(Principled::default()),
+            Material::Principled(principled),
+        ];
+        for material in materials {
+            assert_eq!(Material::decode(material.encode()), Some(material));
+            assert!(material.is_valid(), "{material:?} is not valid");
+        }
+    }
+
+    #[test]
+    fn invalid_layouts() {
+        let layouts = [
+            // a diffuse material with something in the unused vec4s
+            [[0.5, 0.5, 0.5, INF], [1.0, 0.0, 0.0, 0.0], [0.0; 4], [0.0; 4]],
+            [[0.5, 0.5, 0.5, INF], [0.0; 4], [1.0, 0.0, 0.0, 0.0], [0.0; 4]],
+            [[0.5, 0.5, 0.5, INF], [0.0; 4], [0.0; 4], [0.0, 0.0, 0.0, 1.0]],
+            // an emissive material only has a color in `data`
+            [[1.0, 1.0, 1.0, -INF], [0.0; 4], [0.0; 4], [1.0, 1.0, 1.0, 0.0]],
+            // metal fuzz can't be nan
+            [[0.5, 0.5, 0.5, f32::NAN], [0.0; 4], [0.0; 4], [0.0; 4]],
+            // an unknown kind
+            [[0.5, 0.5, 0.5, 0.5], [0.0, 0.0, 0.0, 7.0], [0.0; 4], [0.0; 4]],
+            // ggx metals don't absorb and nothing but principled materials emit
+            [[0.5, 0.5, 0.5, 0.5], [0.0, 0.0, 0.0, GGX_METAL], [0.5, 0.5, 0.5, 1.0], [0.0; 4]],
+            [[1.5, 0.5, 0.0, 0.0], [0.0, 0.0, 0.0, ROUGH_DIELECTRIC], [1.0; 4], [1.0, 0.0, 0.0, 0.0]],
+            [[1.5, INF, INF, INF], [0.0; 4], [1.0; 4], [1.0, 0.0, 0.0, 0.0]],
+        ];
+        for layout in layouts {
+            assert_eq!(Material::decode(layout), None, "{layout:?} decoded");
+        }
+    }
+}
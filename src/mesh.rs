@@ -0,0 +1,118 @@
+use std::fmt::Display;
+
+use vek::{Vec2 as TVec2, Vec3 as TVec3};
+
+use crate::{bvh::Bvh, cache::{read_cache, write_cache}, gltf::{load_gltf, GltfError}, mtl::MtlMaterial, obj::{generate_normals, Group, Obj, ObjError, Tri}, ply::load_ply, scene::Camera, stl::load_stl};
+
+type Vec2 = TVec2<f32>;
+type Vec3 = TVec3<f32>;
+
+pub struct LoadedMesh {
+    pub obj: Obj,
+    /// only gltf files can have a camera
+    pub camera: Option<Camera>,
+    /// over `obj.tris` in model space, see `Bvh::build` for how it is reused in the scene
+    pub bvh: Bvh,
+}
+
+/// loads an .obj, .gltf/.glb, .ply or .stl file by its extension, see `Obj::load_from_file` for the arguments
+///
+/// with `cache` the model space mesh and its bvh are reused from the file written by `write_cache` next to `path`
+pub fn load_mesh(path: &str, pos: Vec3, rot: Vec3, scale: f32, crease_angle: Option<f32>, cache: bool) -> Result<LoadedMesh, MeshError> {
+    let mut mesh = match cache.then(|| read_cache(path, crease_angle)).flatten() {
+        Some(x) => x,
+        None => {
+            let mesh = load_model_space(path, crease_angle)?;
+            if cache {
+                if let Err(x) = write_cache(path, crease_angle, &mesh) {
+                    eprintln!("warning: failed to write mesh cache for {path}: {x}");
+                }
+            }
+            mesh
+        },
+    };
+    (mesh.obj.pos, mesh.obj.rot, mesh.obj.scale) = (pos, rot, scale);
+    mesh.obj.apply_transform();
+    let model = mesh.obj.get_model_world_matrix();
+    mesh.camera = mesh.camera.map(|x| Camera {
+        lookfrom: model.mul_point(Vec3::from(x.lookfrom)).into_array(),
+        lookat: model.mul_point(Vec3::from(x.lookat)).into_array(),
+        vfov: x.vfov,
+        vup: model.mul_direction(Vec3::from(x.vup)).normalized().into_array(),
+    });
+    Ok(mesh)
+}
+
+fn load_model_space(path: &str, crease_angle: Option<f32>) -> Result<LoadedMesh, MeshError> {
+    let (pos, rot, scale) = (Vec3::zero(), Vec3::zero(), 1.0);
+    let (obj, camera) = match path.rsplit_once('.').map(|x| x.1.to_ascii_lowercase()).as_deref() {
+        Some("obj") => (Obj::load_from_file(path, pos, rot, scale, crease_angle).map_err(MeshError::Obj)?, None),
+        Some("gltf" | "glb") => load_gltf(path, pos, rot, scale, crease_angle).map_err(MeshError::Gltf)?,
+        Some(x @ ("ply" | "stl")) => {
+            let parts = if x == "ply" { vec![("".to_string(), load_ply(path)?)] } else { load_stl(path)? };
+            let mut tris = vec![];
+            let mut groups = vec![];
+            for (name, mesh) in parts {
+                let start = tris.len();
+                tris.extend(mesh.tris(crease_angle));
+                groups.push(Group { name, tris: start..tris.len() });
+            }
+            // neither format has materials
+            (Obj { tris, materials: vec![MtlMaterial::default().to_textured()], groups, pos, rot, scale }, None)
+        },
+        _ => return Err(MeshError::UnknownFormat(path.to_string())),
+    };
+    // point clouds like vertex only ply scans have nothing to render
+    if obj.tris.is_empty() {
+        return Err(MeshError::Format(path.to_string(), "no faces".to_string()));
+    }
+    let bvh = Bvh::build(&[], &obj.tris, &[]);
+    Ok(LoadedMesh { obj, camera, bvh })
+}
+
+/// an indexed triangle mesh with optional per vertex attributes
+#[derive(Default)]
+pub struct IndexedMesh {
+    pub positions: Vec<Vec3>,
+    pub faces: Vec<[usize; 3]>,
+    pub normals: Option<Vec<Vec3>>,
+    pub texcoords: Option<Vec<Vec2>>,
+}
+impl IndexedMesh {
+    /// normals are generated like for obj faces without `vn` if the mesh has none
+    pub fn tris(&self, crease_angle: Option<f32>) -> Vec<Tri> {
+        let normals = match &self.normals {
+            Some(normals) => self.faces.iter().map(|f| f.map(|i| normals[i])).collect(),
+            None => generate_normals(&self.positions, &self.faces, &vec![None; self.faces.len()], crease_angle),
+        };
+        self.faces.iter().zip(normals).map(|(f, [an, bn, cn])| {
+            let [a, b, c] = f.map(|i| self.positions[i]);
+            let mut t = Tri::new(a, b, c, an, bn, cn);
+            if let Some(uv) = &self.texcoords {
+                [t.at, t.bt, t.ct] = f.map(|i| uv[i]);
+            }
+            t
+        }).collect()
+    }
+}
+
+#[derive(Debug)]
+pub enum MeshError {
+    Obj(ObjError),
+    Gltf(GltfError),
+    Io(String, std::io::Error),
+    /// path and what is wrong with it
+    Format(String, String),
+    UnknownFormat(String),
+}
+impl Display for MeshError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            MeshError::Obj(err) => write!(f, "{err}"),
+            MeshError::Gltf(err) => write!(f, "{err}"),
+            MeshError::Io(path, err) => write!(f, "{path}: {err}"),
+            MeshError::Format(path, reason) => write!(f, "{path}: {reason}"),
+            MeshError::UnknownFormat(path) => write!(f, "{path}: unknown mesh format, expected .obj, .gltf, .glb, .ply or .stl"),
+        }
+    }
+}
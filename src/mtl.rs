@@ -0,0 +1,115 @@
+use std::{collections::{HashMap, HashSet}, fs::read_to_string, path::Path};
+
+use crate::{material::{Material, TexturedMaterial}, obj::{ObjError, ObjErrorReason}};
+
+/// the subset of a .mtl material the renderer understands
+#[derive(Clone)]
+pub struct MtlMaterial {
+    pub kd: [f32; 3],
+    pub ks: [f32; 3],
+    pub ns: f32,
+    pub ni: f32,
+    pub d: f32,
+    pub ke: [f32; 3],
+    pub illum: u32,
+    /// relative to the working directory
+    pub map_kd: Option<String>,
+}
+impl Default for MtlMaterial {
+    fn default() -> Self {
+        Self { kd: [0.8; 3], ks: [0.0; 3], ns: 0.0, ni: 1.0, d: 1.0, ke: [0.0; 3], illum: 2, map_kd: None }
+    }
+}
+impl MtlMaterial {
+    /// emission wins over transparency, which wins over reflection, anything else is diffuse
+    pub fn to_material(&self) -> Material {
+        if self.ke.iter().any(|&x| x > 0.0) {
+            return Material::Emissive { color: self.ke };
+        }
+        if self.d < 1.0 || matches!(self.illum, 4 | 6 | 7 | 9) {
+            // an ior of 1 means no refraction at all, which is what exporters write when they don't know
+            let ior = if self.ni > 1.0 { self.ni } else { 1.5 };
+            return Material::Dielectric { ior, absorption: [1.0; 3], absorption_distance: 1.0 };
+        }
+        if matches!(self.illum, 3 | 5 | 8) {
+            let albedo = if self.ks.iter().any(|&x| x > 0.0) { self.ks } else { self.kd };
+            // blinn-phong exponent to an approximate roughness
+            let fuzz = (2.0 / (self.ns.max(0.0) + 2.0)).sqrt();
+            return Material::Metal { albedo, fuzz };
+        }
+        Material::Lambertian { albedo: self.kd }
+    }
+    pub fn to_textured(&self) -> TexturedMaterial {
+        TexturedMaterial { material: self.to_material(), albedo_map: self.map_kd.clone() }
+    }
+}
+
+/// loads every material in a .mtl file by name, statements the renderer doesn't use are skipped with a warning
+pub fn load_mtl(path: &str) -> Result<HashMap<String, MtlMaterial>, ObjError> {
+    let file = read_to_string(path).map_err(|x| ObjError { path: path.to_string(), line: 0, reason: ObjErrorReason::Io(x) })?;
+    let mut materials = HashMap::new();
+    let mut current: Option<(String, MtlMaterial)> = None;
+    let dir = Path::new(path).parent().unwrap_or(Path::new(""));
+    // exporters write the same extensions for every material, so only warn the first time
+    let mut skipped = HashSet::new();
+    for (line, x) in file.lines().enumerate() {
+        let err = |reason| ObjError { path: path.to_string(), line: line + 1, reason };
+        let mut skip = |x: &str| {
+            if skipped.insert(x.to_string()) {
+                eprintln!("warning: {}, skipping it", err(ObjErrorReason::UnsupportedDirective(x.to_string())));
+            }
+        };
+        let mut words = x.split_ascii_whitespace();
+        let Some(directive) = words.next() else {
+            continue;
+        };
+        if directive.starts_with('#') {
+            continue;
+        }
+        if directive == "newmtl" {
+            let name = words.collect::<Vec<_>>().join(" ");
+            if let Some((name, material)) = current.replace((name, MtlMaterial::default())) {
+                materials.insert(name, material);
+            }
+            continue;
+        }
+        // nothing to apply it to before the first newmtl
+        let Some((_, material)) = current.as_mut() else {
+            skip(directive);
+            continue;
+        };
+        match directive {
+            "Kd" => material.kd = parse_rgb(words).map_err(err)?,
+            "Ks" => material.ks = parse_rgb(words).map_err(err)?,
+            "Ke" => material.ke = parse_rgb(words).map_err(err)?,
+            "Ns" => material.ns = parse_f32(words.next()).map_err(err)?,
+            "Ni" => material.ni = parse_f32(words.next()).map_err(err)?,
+            "d" => material.d = parse_f32(words.next()).map_err(err)?,
+            "Tr" => material.d = 1.0 - parse_f32(words.next()).map_err(err)?,
+            "illum" => material.illum = parse_f32(words.next()).map_err(err)? as u32,
+            // options like -bm come before the file name, which is always last and relative to the mtl file
+            "map_Kd" => material.map_kd = words.last().map(|x| dir.join(x).to_string_lossy().into_owned()),
+            // not needed for rendering
+            "Ka" | "Tf" | "map_Ka" | "map_Ks" | "map_Ns" | "map_d" | "map_Bump" | "map_bump" | "bump" | "disp" | "decal" | "refl" => {},
+            x => skip(x),
+        }
+    }
+    if let Some((name, material)) = current {
+        materials.insert(name, material);
+    }
+    Ok(materials)
+}
+
+fn parse_f32(word: Option<&str>) -> Result<f32, ObjErrorReason> {
+    let word = word.ok_or(ObjErrorReason::MissingComponent)?;
+    word.parse::<f32>().map_err(|_| ObjErrorReason::BadNumber(word.to_string()))
+}
+
+/// a single value is used for all three channels
+fn parse_rgb<'a>(mut words: impl Iterator<Item = &'a str>) -> Result<[f32; 3], ObjErrorReason> {
+    let r = parse_f32(words.next())?;
+    match words.next() {
+        None => Ok([r; 3]),
+        g => Ok([r, parse_f32(g)?, parse_f32(words.next())?]),
+    }
+}
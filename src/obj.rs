@@ -1,12 +1,18 @@
-use std::fs::read_to_string;
-use vek::Vec3 as TVec3;
+use std::{collections::{HashMap, HashSet}, fmt::Display, fs::read_to_string, ops::Range, path::Path};
+use vek::{Mat4 as TMat4, Vec2 as TVec2, Vec3 as TVec3};
 
+use crate::{material::TexturedMaterial, mtl::{load_mtl, MtlMaterial}};
+
+type Vec2 = TVec2<f32>;
 type Vec3 = TVec3<f32>;
+type Mat4 = TMat4<f32>;
 
+#[derive(Clone, Copy)]
 #[repr(C)]
 pub struct Tri {
     pub a: Vec3,
-    pub _a: f32,
+    /// index into `Obj::materials` after loading, into the material buffer once in a scene
+    pub mat: u32,
     pub b: Vec3,
     pub _b: f32,
     pub c: Vec3,
@@ -18,152 +24,415 @@ pub struct Tri {
     pub _e: f32,
     pub cn: Vec3,
     pub _f: f32,
+
+    pub at: Vec2,
+    pub bt: Vec2,
+    pub ct: Vec2,
+    pub _g: Vec2,
 }
 impl Tri {
     pub fn new(a: Vec3, b: Vec3, c: Vec3, an: Vec3, bn: Vec3, cn: Vec3) -> Self {
-        Self { a, _a: 0.0, b, _b: 0.0, c, _c: 0.0, an, _d: 0.0, bn, _e: 0.0, cn, _f: 0.0 }
+        Self { a, mat: 0, b, _b: 0.0, c, _c: 0.0, an, _d: 0.0, bn, _e: 0.0, cn, _f: 0.0, at: Vec2::zero(), bt: Vec2::zero(), ct: Vec2::zero(), _g: Vec2::zero() }
+    }
+    /// `normal` should be the inverse transpose of `model`
+    pub fn transformed(&self, model: Mat4, normal: Mat4) -> Self {
+        Self {
+            a: model.mul_point(self.a),
+            b: model.mul_point(self.b),
+            c: model.mul_point(self.c),
+            an: normal.mul_direction(self.an).normalized(),
+            bn: normal.mul_direction(self.bn).normalized(),
+            cn: normal.mul_direction(self.cn).normalized(),
+            ..*self
+        }
     }
 }
 
+/// a named `o`/`g` section of an obj, faces before the first one are in a group named ""
+pub struct Group {
+    pub name: String,
+    pub tris: Range<usize>,
+}
+
 pub struct Obj {
+    /// sorted by group
     pub tris: Vec<Tri>,
+    /// from the mtl libraries, faces without `usemtl` get a default diffuse material
+    pub materials: Vec<TexturedMaterial>,
+    pub groups: Vec<Group>,
     pub pos: Vec3,
     pub rot: Vec3,
     pub scale: f32
 }
 impl Obj {
-    pub fn load_from_file(path: &str, pos: Vec3, rot: Vec3, scale: f32) -> Self {
+    /// `rot` is in radians and applied around x, then y, then z
+    ///
+    /// faces without normals are flat shaded unless they are in a smoothing group (`s`), or smoothed across edges
+    /// sharper than `crease_angle` (radians) if given
+    pub fn load_from_file(path: &str, pos: Vec3, rot: Vec3, scale: f32, crease_angle: Option<f32>) -> Result<Self, ObjError> {
+        let file = read_to_string(path).map_err(|x| ObjError { path: path.to_string(), line: 0, reason: ObjErrorReason::Io(x) })?;
+        Self::load_from_str(path, &file, pos, rot, scale, crease_angle)
+    }
+    /// `path` is for errors and finding mtl libraries
+    fn load_from_str(path: &str, file: &str, pos: Vec3, rot: Vec3, scale: f32, crease_angle: Option<f32>) -> Result<Self, ObjError> {
         let mut tris = Vec::new();
+        let mut faces = vec![];
         let mut normals = vec![];
+        let mut texcoords = vec![];
         let mut positions = vec![];
-        let file = read_to_string(path).unwrap();
-        for x in file.split('\n') {
-            if x.len() == 0 {
-                continue;
-            }
-            if x.starts_with('#') {
-                continue;
-            }
-            if x.starts_with("vn") {
-                let mut v = Vec3::new(0.0, 0.0, 0.0);
-                for (i, c) in x.split_ascii_whitespace().skip(1).enumerate() {
-                    v[i] = c.parse::<f32>().unwrap();
-                }
-                normals.push(v.normalized());
-            }
-            if x.starts_with('v') && !x.contains("vt") {
-                let mut v = Vec3::new(0.0, 0.0, 0.0);
-                for (i, c) in x.split_ascii_whitespace().skip(1).enumerate() {
-                    v[i] = c.parse::<f32>().unwrap();
-                }
-                positions.push(v);
-            }
-            if x.starts_with('f') {
-                if x.split_ascii_whitespace().skip(1).next().unwrap().chars().fold(0, |c, x| {if x == '/' {c+1} else {c}})>1 {
-                    let mut f: Vec<Vertex> = x.split_ascii_whitespace().skip(1).map(|f| f.split('/').map(|x| x.parse::<usize>().unwrap()).collect()).map(|x: Vec<_>| Vertex{ pos: positions[x[0]-1], norm: normals[x[2]-1], }).collect();
-                    if f.len()==3 {
-                        let t = Tri::new(f[0].pos, f[1].pos, f[2].pos, f[0].norm, f[1].norm, f[2].norm);
-                        tris.push(t);
-                    } else if f.len()==4 {
-                        let t = Tri::new(f[2].pos, f[3].pos, f[0].pos, f[2].norm, f[3].norm, f[0].norm);
-                        let t2 = Tri::new(f[0].pos, f[1].pos, f[2].pos, f[0].norm, f[1].norm, f[2].norm);
-                        tris.push(t);
-                        tris.push(t2);
+        let mut library = HashMap::new();
+        let mut materials = vec![];
+        let mut material_ids = HashMap::new();
+        let mut current = None;
+        let mut groups = vec![];
+        let mut group_ids = HashMap::new();
+        let (mut object, mut group) = (String::new(), None::<String>);
+        let mut smooth = None;
+        // like in mtl files, only warn the first time
+        let mut skipped = HashSet::new();
+        for (line, x) in file.lines().enumerate() {
+            let err = |reason| ObjError { path: path.to_string(), line: line + 1, reason };
+            let mut words = x.split_ascii_whitespace();
+            match words.next() {
+                None => {},
+                Some(x) if x.starts_with('#') => {},
+                Some("v") => positions.push(parse_vec3(words).map_err(err)?),
+                Some("vn") => normals.push(parse_vec3(words).map_err(err)?.normalized()),
+                Some("vt") => texcoords.push(parse_vec2(words).map_err(err)?),
+                Some("f") => {
+                    let f = words.map(|f| {
+                        let mut idx = f.split('/');
+                        let v = resolve_index(idx.next().unwrap_or(""), positions.len())?;
+                        let mut optional = |count| idx.next().filter(|x| !x.is_empty()).map(|x| resolve_index(x, count)).transpose();
+                        let vt = optional(texcoords.len())?;
+                        let vn = optional(normals.len())?;
+                        Ok(Corner { v, vt, vn })
+                    }).collect::<Result<Vec<_>, _>>().map_err(err)?;
+                    if f.len() < 3 {
+                        return Err(err(ObjErrorReason::TooFewVertices(f.len())));
+                    }
+                    let mat = *current.get_or_insert_with(|| {
+                        materials.push(MtlMaterial::default().to_textured());
+                        materials.len() - 1
+                    });
+                    // `g` names are nested in the current `o`
+                    let name = match &group {
+                        Some(g) if !object.is_empty() => format!("{object}/{g}"),
+                        Some(g) => g.clone(),
+                        None => object.clone(),
+                    };
+                    let group = *group_ids.entry(name.clone()).or_insert_with(|| {
+                        groups.push(name);
+                        groups.len() - 1
+                    });
+                    let points: Vec<Vec3> = f.iter().map(|x| positions[x.v]).collect();
+                    faces.extend(triangulate(&points).into_iter().map(|t| Face { corners: t.map(|i| f[i]), mat, group, smooth }));
+                },
+                Some("o") => {
+                    object = words.collect::<Vec<_>>().join(" ");
+                    group = None;
+                },
+                Some("g") => group = Some(words.collect::<Vec<_>>().join(" ")),
+                Some("s") => smooth = match words.next() {
+                    Some("off") => Some(0),
+                    Some(x) => Some(x.parse::<u32>().map_err(|_| err(ObjErrorReason::BadNumber(x.to_string())))?),
+                    None => return Err(err(ObjErrorReason::MissingComponent)),
+                },
+                Some("mtllib") => {
+                    // relative to the obj file, objs are often shared without their mtl so a missing one only warns
+                    let dir = Path::new(path).parent().unwrap_or(Path::new(""));
+                    for name in words {
+                        match load_mtl(&dir.join(name).to_string_lossy()) {
+                            Ok(x) => library.extend(x),
+                            Err(ObjError { reason: ObjErrorReason::Io(x), path, .. }) => eprintln!("{path}: {x}, using default materials"),
+                            Err(x) => return Err(x),
+                        }
                     }
-                } else {
-                    unimplemented!();
-                    // let mut f: Vec<Vertex> = x.split_ascii_whitespace().skip(1).map(|f| f.split('/').map(|x| x.parse::<usize>().unwrap()).collect()).map(|x: Vec<_>| Vertex{ pos: positions[x[0]-1], norm: normals[x[2]-1], }).collect();
-                    // if f.len()==3 {
-                        // let t = Tri::new(f[0].pos, f[1].pos, f[2].pos, f[0].norm, f[1].norm, f[2].norm);
-                        // f.iter_mut().for_each(|x| {x.norm = t.normal().into()});
-                        // tris.push(t);
-                    // } else if f.len()==4 {
-                        // let t = Tri::new(f[2].pos, f[3].pos, f[0].pos, f[2].norm, f[3].norm, f[0].norm);
-                        // let t2 = Tri::new(f[0].pos, f[1].pos, f[2].pos, f[0].norm, f[1].norm, f[2].norm);
-                        // f.iter_mut().for_each(|x| {x.norm = t.normal().into()});
-                        // tris.push(t);
-                        // tris.push(t2);
-                        // tris.extend([f[2], f[3], f[0]]);
-                    // }
-                }
+                },
+                Some("usemtl") => {
+                    let name = words.collect::<Vec<_>>().join(" ");
+                    let material = library.get(&name).cloned().unwrap_or_default();
+                    current = Some(*material_ids.entry(name).or_insert_with(|| {
+                        materials.push(material.to_textured());
+                        materials.len() - 1
+                    }));
+                },
+                // not needed for rendering
+                Some("l" | "p") => {},
+                Some(x) if skipped.insert(x) => eprintln!("warning: {}, skipping it", err(ObjErrorReason::UnsupportedDirective(x.to_string()))),
+                Some(_) => {},
             }
         }
-        // let tris: Vec<_> = tris.iter().map(|x| {[x.pos, x.norm]}).flatten().map(|x| {[x.x, x.y, x.z]}).flatten().collect();
-
-        Self { tris, pos, rot, scale }
-    }
-    // pub fn get_model_world_matrix(&self) -> Matrix4<f32> {
-    //     use crate::camera::{cos, sin};
-    //     let cx=cos(self.rot[0]);
-    //     let sx=sin(self.rot[0]);
-    //     let cy=cos(self.rot[1]);
-    //     let sy=sin(self.rot[1]);
-    //     let cz=cos(self.rot[2]);
-    //     let sz=sin(self.rot[2]);
-
-    //     Matrix4::new(
-    //         1.0, 0.0, 0.0, self.pos.x,
-    //         0.0, 1.0, 0.0, self.pos.y,
-    //         0.0, 0.0, 1.0, self.pos.z,
-    //         0.0, 0.0, 0.0, 1.0
-    //     )*
-    //     Matrix4::new(
-    //         1.0, 0.0, 0.0, 0.0,
-    //         0.0, 1.0, 0.0, 0.0,
-    //         0.0, 0.0, 1.0, 0.0,
-    //         0.0, 0.0, 0.0, 1.0/self.scale
-    //     )*
-    //     Matrix4::new(
-    //         cz, -sz, 0.0, 0.0,
-    //         sz, cz, 0.0, 0.0,
-    //         0.0, 0.0, 1.0, 0.0,
-    //         0.0, 0.0, 0.0, 1.0
-    //     )*
-    //     Matrix4::new(
-    //         cy, 0.0, sy, 0.0,
-    //         0.0, 1.0, 0.0, 0.0,
-    //         -sy, 0.0, cy, 0.0,
-    //         0.0, 0.0, 0.0, 1.0
-    //     )*
-    //     Matrix4::new(
-    //         1.0, 0.0, 0.0, 0.0,
-    //         0.0, cx, -sx, 0.0,
-    //         0.0, sx, cx, 0.0,
-    //         0.0, 0.0, 0.0, 1.0
-    //     )
-    // }
-    // pub fn get_model_rotation_matrix(&self) -> Matrix4<f32> {
-    //     use crate::camera::{cos, sin};
-    //     let cx=cos(self.rot[0]);
-    //     let sx=sin(self.rot[0]);
-    //     let cy=cos(self.rot[1]);
-    //     let sy=sin(self.rot[1]);
-    //     let cz=cos(self.rot[2]);
-    //     let sz=sin(self.rot[2]);
-    //     Matrix4::new(
-    //         cz, -sz, 0.0, 0.0,
-    //         sz, cz, 0.0, 0.0,
-    //         0.0, 0.0, 1.0, 0.0,
-    //         0.0, 0.0, 0.0, 1.0
-    //     )*
-    //     Matrix4::new(
-    //         cy, 0.0, sy, 0.0,
-    //         0.0, 1.0, 0.0, 0.0,
-    //         -sy, 0.0, cy, 0.0,
-    //         0.0, 0.0, 0.0, 1.0
-    //     )*
-    //     Matrix4::new(
-    //         1.0, 0.0, 0.0, 0.0,
-    //         0.0, cx, -sx, 0.0,
-    //         0.0, sx, cx, 0.0,
-    //         0.0, 0.0, 0.0, 1.0
-    //     )
-    // }
+
+        // faces missing any vn get generated normals instead
+        let (with_normals, without_normals): (Vec<_>, Vec<_>) = faces.into_iter().partition(|f| f.corners.iter().all(|x| x.vn.is_some()));
+        let corners: Vec<[usize; 3]> = without_normals.iter().map(|f| f.corners.map(|x| x.v)).collect();
+        let smoothing: Vec<Option<u32>> = without_normals.iter().map(|f| f.smooth).collect();
+        let generated = without_normals.into_iter().zip(generate_normals(&positions, &corners, &smoothing, crease_angle));
+        let with_normals = with_normals.into_iter().map(|f| (f, f.corners.map(|x| normals[x.vn.unwrap()])));
+        let mut grouped: Vec<(usize, Tri)> = with_normals.chain(generated).map(|(f, [an, bn, cn])| {
+            let [a, b, c] = f.corners.map(|x| positions[x.v]);
+            let mut t = Tri::new(a, b, c, an, bn, cn);
+            t.mat = f.mat as u32;
+            [t.at, t.bt, t.ct] = f.corners.map(|x| x.vt.map_or(Vec2::zero(), |i| texcoords[i]));
+            (f.group, t)
+        }).collect();
+        grouped.sort_by_key(|x| x.0);
+        let groups = groups.into_iter().enumerate().map(|(i, name)| {
+            let start = grouped.partition_point(|x| x.0 < i);
+            let end = grouped.partition_point(|x| x.0 <= i);
+            Group { name, tris: start..end }
+        }).collect();
+        tris.extend(grouped.into_iter().map(|x| x.1));
+
+        let mut obj = Self { tris, materials, groups, pos, rot, scale };
+        obj.apply_transform();
+        Ok(obj)
+    }
+    /// moves `tris` from model space to world space
+    pub fn apply_transform(&mut self) {
+        let model = self.get_model_world_matrix();
+        let normal = model.inverted().transposed();
+        self.tris.iter_mut().for_each(|x| *x = x.transformed(model, normal));
+    }
+    pub fn get_model_world_matrix(&self) -> Mat4 {
+        Mat4::translation_3d(self.pos) * Mat4::scaling_3d(Vec3::broadcast(self.scale)) * self.get_model_rotation_matrix()
+    }
+    pub fn get_model_rotation_matrix(&self) -> Mat4 {
+        Mat4::rotation_z(self.rot.z) * Mat4::rotation_y(self.rot.y) * Mat4::rotation_x(self.rot.x)
+    }
+}
+
+fn parse_vec3<'a>(mut words: impl Iterator<Item = &'a str>) -> Result<Vec3, ObjErrorReason> {
+    let mut v = Vec3::zero();
+    for i in 0..3 {
+        let word = words.next().ok_or(ObjErrorReason::MissingComponent)?;
+        v[i] = word.parse::<f32>().map_err(|_| ObjErrorReason::BadNumber(word.to_string()))?;
+    }
+    Ok(v)
+}
+
+/// `vt u [v [w]]`, w is not used
+fn parse_vec2<'a>(mut words: impl Iterator<Item = &'a str>) -> Result<Vec2, ObjErrorReason> {
+    let word = words.next().ok_or(ObjErrorReason::MissingComponent)?;
+    let u = word.parse::<f32>().map_err(|_| ObjErrorReason::BadNumber(word.to_string()))?;
+    let v = words.next().map_or(Ok(0.0), |x| x.parse::<f32>().map_err(|_| ObjErrorReason::BadNumber(x.to_string())))?;
+    Ok(Vec2::new(u, v))
+}
+
+/// obj indices start at 1, negative indices count back from the last element read so far
+fn resolve_index(x: &str, count: usize) -> Result<usize, ObjErrorReason> {
+    let i = x.parse::<isize>().map_err(|_| ObjErrorReason::BadNumber(x.to_string()))?;
+    let index = if i < 0 { count as isize + i } else { i - 1 };
+    if index < 0 || index >= count as isize {
+        return Err(ObjErrorReason::IndexOutOfRange(i, count));
+    }
+    Ok(index as usize)
+}
+
+/// splits a planar polygon into triangles by ear clipping, so concave faces are handled too
+pub fn triangulate(points: &[Vec3]) -> Vec<[usize; 3]> {
+    if points.len() < 3 {
+        return vec![];
+    }
+    if points.len() == 3 {
+        return vec![[0, 1, 2]];
+    }
+    // newell's method gives a stable normal even for concave polygons, drop its largest axis to project to 2d
+    let normal: Vec3 = (0..points.len()).map(|i| {
+        let (a, b) = (points[i], points[(i + 1) % points.len()]);
+        Vec3::new((a.y - b.y) * (a.z + b.z), (a.z - b.z) * (a.x + b.x), (a.x - b.x) * (a.y + b.y))
+    }).sum();
+    let axis = if normal.x.abs() > normal.y.abs() && normal.x.abs() > normal.z.abs() { 0 } else if normal.y.abs() > normal.z.abs() { 1 } else { 2 };
+    let (u, v) = ((axis + 1) % 3, (axis + 2) % 3);
+    let sign = normal[axis].signum();
+    let p: Vec<[f32; 2]> = points.iter().map(|x| [x[u], x[v]]).collect();
+    let cross = |a: [f32; 2], b: [f32; 2], c: [f32; 2]| ((b[0] - a[0]) * (c[1] - a[1]) - (b[1] - a[1]) * (c[0] - a[0])) * sign;
+
+    let mut remaining: Vec<usize> = (0..points.len()).collect();
+    let mut tris = Vec::with_capacity(points.len() - 2);
+    while remaining.len() > 3 {
+        let n = remaining.len();
+        let ear = (0..n).find(|&i| {
+            let (a, b, c) = (remaining[(i + n - 1) % n], remaining[i], remaining[(i + 1) % n]);
+            if cross(p[a], p[b], p[c]) <= 0.0 {
+                return false;
+            }
+            remaining.iter().filter(|&&x| x != a && x != b && x != c).all(|&x| {
+                cross(p[a], p[b], p[x]) < 0.0 || cross(p[b], p[c], p[x]) < 0.0 || cross(p[c], p[a], p[x]) < 0.0
+            })
+        });
+        // degenerate polygons have no ear, clip the first vertex so we always make progress
+        let i = ear.unwrap_or(0);
+        tris.push([remaining[(i + n - 1) % n], remaining[i], remaining[(i + 1) % n]]);
+        remaining.remove(i);
+    }
+    tris.push([remaining[0], remaining[1], remaining[2]]);
+    tris
+}
+
+/// per corner normals for `faces`, each corner averages the area weighted normals of the faces sharing its position
+/// that are in the same smoothing group and within `crease_angle` of this face. faces in smoothing group 0, or without
+/// a group when no `crease_angle` is given, are flat
+pub fn generate_normals(positions: &[Vec3], faces: &[[usize; 3]], smoothing: &[Option<u32>], crease_angle: Option<f32>) -> Vec<[Vec3; 3]> {
+    let face_normals: Vec<Vec3> = faces.iter().map(|f| (positions[f[1]] - positions[f[0]]).cross(positions[f[2]] - positions[f[0]])).collect();
+    let flat = |i: usize| face_normals[i].try_normalized().unwrap_or(Vec3::unit_y());
+    let threshold = |i: usize| match (smoothing[i], crease_angle) {
+        (Some(0), _) | (None, None) => None,
+        (_, Some(angle)) => Some(angle.cos()),
+        (Some(_), None) => Some(-1.0),
+    };
+
+    let mut adjacent = vec![vec![]; positions.len()];
+    for (i, f) in faces.iter().enumerate() {
+        for &v in f {
+            adjacent[v].push(i);
+        }
+    }
+    faces.iter().enumerate().map(|(i, f)| {
+        let own = flat(i);
+        let Some(threshold) = threshold(i) else {
+            return [own; 3];
+        };
+        f.map(|v| {
+            let sum: Vec3 = adjacent[v].iter().filter(|&&j| smoothing[j] == smoothing[i] && flat(j).dot(own) >= threshold).map(|&j| face_normals[j]).sum();
+            sum.try_normalized().unwrap_or(own)
+        })
+    }).collect()
+}
+
+#[derive(Debug)]
+pub struct ObjError {
+    pub path: String,
+    /// 1 based, 0 if the file could not be read
+    pub line: usize,
+    pub reason: ObjErrorReason,
+}
+#[derive(Debug)]
+pub enum ObjErrorReason {
+    Io(std::io::Error),
+    BadNumber(String),
+    MissingComponent,
+    /// the index as written and the number of elements it could refer to
+    IndexOutOfRange(isize, usize),
+    TooFewVertices(usize),
+    UnsupportedDirective(String),
+}
+impl Display for ObjError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}:{}: ", self.path, self.line)?;
+        match &self.reason {
+            ObjErrorReason::Io(err) => write!(f, "{err}"),
+            ObjErrorReason::BadNumber(x) => write!(f, "{x:?} is not a valid number"),
+            ObjErrorReason::MissingComponent => write!(f, "missing value"),
+            ObjErrorReason::IndexOutOfRange(i, count) => write!(f, "index {i} is out of range, only {count} elements are defined"),
+            ObjErrorReason::TooFewVertices(count) => write!(f, "face has {count} vertices, at least 3 are needed"),
+            ObjErrorReason::UnsupportedDirective(x) => write!(f, "unsupported directive {x:?}"),
+        }
+    }
 }
 
 #[derive(Clone, Copy)]
-#[repr(C)]
-pub struct Vertex {
-    pub pos: Vec3,
-    pub norm: Vec3,
-}
\ No newline at end of file
+struct Face {
+    corners: [Corner; 3],
+    mat: usize,
+    group: usize,
+    /// smoothing group, `None` if the file never set one
+    smooth: Option<u32>,
+}
+
+/// indices of one face corner, texture coordinates and normals are optional
+#[derive(Clone, Copy)]
+struct Corner {
+    v: usize,
+    vt: Option<usize>,
+    vn: Option<usize>,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn load(file: &str) -> Result<Obj, ObjError> {
+        Obj::load_from_str("test.obj", file, Vec3::zero(), Vec3::zero(), 1.0, None)
+    }
+
+    fn area(a: Vec3, b: Vec3, c: Vec3) -> Vec3 {
+        (b - a).cross(c - a) / 2.0
+    }
+
+    #[test]
+    fn concave_ngon() {
+        // an L in the xz plane, so the projection has to come from the newell normal rather than dropping z
+        let obj = load("v 0 0 0\nv 0 0 2\nv 1 0 2\nv 1 0 1\nv 2 0 1\nv 2 0 0\nf 1 2 3 4 5 6\n").unwrap();
+        assert_eq!(obj.tris.len(), 4);
+        // every triangle faces the same way and together they cover the L exactly once
+        let areas: Vec<Vec3> = obj.tris.iter().map(|t| area(t.a, t.b, t.c)).collect();
+        assert!(areas.iter().all(|x| x.y > 0.0 && x.x == 0.0 && x.z == 0.0), "{areas:?}");
+        assert_eq!(areas.iter().map(|x| x.y).sum::<f32>(), 3.0);
+    }
+
+    #[test]
+    fn triangulate_concave() {
+        // an arrow with its notch last, where a fan from the first vertex would cover the notch and flip a triangle
+        let points = [[0.0, 0.0, 0.0], [2.0, 0.5, 0.0], [0.0, 1.0, 0.0], [1.0, 0.5, 0.0]].map(Vec3::from);
+        let tris = triangulate(&points);
+        assert_eq!(tris.len(), 2);
+        let areas: Vec<f32> = tris.iter().map(|t| area(points[t[0]], points[t[1]], points[t[2]]).z).collect();
+        assert!(areas.iter().all(|&x| x > 0.0), "{tris:?} has triangles facing both ways");
+        assert_eq!(areas.iter().sum::<f32>(), 0.5);
+    }
+
+    #[test]
+    fn negative_indices() {
+        let obj = load("v 0 0 0\nv 1 0 0\nv 0 1 0\nf -3 -2 -1\nv 0 0 1\nf 1 -1 -3\n").unwrap();
+        assert_eq!((obj.tris[0].a, obj.tris[0].b, obj.tris[0].c), (Vec3::zero(), Vec3::unit_x(), Vec3::unit_y()));
+        assert_eq!((obj.tris[1].a, obj.tris[1].b, obj.tris[1].c), (Vec3::zero(), Vec3::unit_z(), Vec3::unit_x()));
+    }
+
+    #[test]
+    fn resolve_indices() {
+        assert_eq!(resolve_index("1", 3).unwrap(), 0);
+        assert_eq!(resolve_index("3", 3).unwrap(), 2);
+        assert_eq!(resolve_index("-1", 3).unwrap(), 2);
+        assert_eq!(resolve_index("-3", 3).unwrap(), 0);
+        for (x, i) in [("0", 0), ("4", 4), ("-4", -4)] {
+            assert!(matches!(resolve_index(x, 3), Err(ObjErrorReason::IndexOutOfRange(a, 3)) if a == i), "{x} resolved");
+        }
+        assert!(matches!(resolve_index("1.5", 3), Err(ObjErrorReason::BadNumber(_))));
+    }
+
+    #[test]
+    fn normals_without_texcoords() {
+        let obj = load("v 0 0 0\nv 1 0 0\nv 0 1 0\nvt 0.5 0.5\nvn 0 0 2\nvn 0 1 1\nf 1//1 2/1/2 3//1\n").unwrap();
+        let t = obj.tris[0];
+        assert_eq!((t.an, t.cn), (Vec3::unit_z(), Vec3::unit_z()));
+        assert!(t.bn.distance(Vec3::new(0.0, 1.0, 1.0).normalized()) < 1e-6);
+        assert_eq!((t.at, t.bt, t.ct), (Vec2::zero(), Vec2::broadcast(0.5), Vec2::zero()));
+    }
+
+    #[test]
+    fn error_lines() {
+        let cases = [
+            ("v 0 0 0\nv 1 0 0\n\n# a comment\nf 1 2 3\n", 5, "test.obj:5: index 3 is out of range, only 2 elements are defined"),
+            ("v 0 0 0\nv 1 0 x\n", 2, "test.obj:2: \"x\" is not a valid number"),
+            ("v 0 0\n", 1, "test.obj:1: missing value"),
+            ("v 0 0 0\nv 1 0 0\nf 1 2\n", 3, "test.obj:3: face has 2 vertices, at least 3 are needed"),
+        ];
+        for (file, line, message) in cases {
+            let err = load(file).err().unwrap();
+            assert_eq!((err.path.as_str(), err.line), ("test.obj", line));
+            assert_eq!(err.to_string(), message);
+        }
+        let err = Obj::load_from_file("missing.obj", Vec3::zero(), Vec3::zero(), 1.0, None).err().unwrap();
+        assert!(matches!(err, ObjError { line: 0, reason: ObjErrorReason::Io(_), .. }) && err.path == "missing.obj");
+    }
+
+    #[test]
+    fn unsupported_directives() {
+        let obj = load("vp 0.5\ncstype bezier\ndeg 3\nv 0 0 0\nv 1 0 0\nv 0 1 0\nbevel off\nf 1 2 3\ncurv 0 1 1 2\n").unwrap();
+        assert_eq!(obj.tris.len(), 1);
+        // statements the renderer uses are still checked
+        assert!(load("vp 0.5\nv 0 0 x\n").is_err());
+    }
+}
@@ -0,0 +1,280 @@
+use std::fs::read;
+
+use vek::{Vec2 as TVec2, Vec3 as TVec3};
+
+use crate::{mesh::{IndexedMesh, MeshError}, obj::triangulate};
+
+type Vec2 = TVec2<f32>;
+type Vec3 = TVec3<f32>;
+
+#[derive(Clone, Copy)]
+enum Scalar {
+    I8,
+    U8,
+    I16,
+    U16,
+    I32,
+    U32,
+    F32,
+    F64,
+}
+impl Scalar {
+    fn parse(x: &str) -> Option<Self> {
+        Some(match x {
+            "char" | "int8" => Scalar::I8,
+            "uchar" | "uint8" => Scalar::U8,
+            "short" | "int16" => Scalar::I16,
+            "ushort" | "uint16" => Scalar::U16,
+            "int" | "int32" => Scalar::I32,
+            "uint" | "uint32" => Scalar::U32,
+            "float" | "float32" => Scalar::F32,
+            "double" | "float64" => Scalar::F64,
+            _ => return None,
+        })
+    }
+    /// size IN BYTES
+    fn size(self) -> usize {
+        match self {
+            Scalar::I8 | Scalar::U8 => 1,
+            Scalar::I16 | Scalar::U16 => 2,
+            Scalar::I32 | Scalar::U32 | Scalar::F32 => 4,
+            Scalar::F64 => 8,
+        }
+    }
+}
+
+enum Property {
+    Scalar(Scalar, String),
+    /// count type, item type
+    List(Scalar, Scalar, String),
+}
+impl Property {
+    fn name(&self) -> &str {
+        match self {
+            Property::Scalar(_, name) | Property::List(_, _, name) => name,
+        }
+    }
+}
+
+struct Element {
+    name: String,
+    count: usize,
+    properties: Vec<Property>,
+}
+impl Element {
+    /// index of the first property called any of `names`
+    fn find(&self, names: &[&str]) -> Option<usize> {
+        self.properties.iter().position(|x| names.contains(&x.name()))
+    }
+}
+
+/// everything after `end_header`, every value is widened to f64 which is exact for all ply types
+enum Body<'a> {
+    Ascii(std::str::SplitAsciiWhitespace<'a>),
+    /// little endian
+    Binary(&'a [u8]),
+}
+impl Body<'_> {
+    fn read(&mut self, ty: Scalar) -> Result<f64, String> {
+        match self {
+            Body::Ascii(words) => {
+                let word = words.next().ok_or("unexpected end of file")?;
+                word.parse::<f64>().map_err(|_| format!("bad number {word:?}"))
+            },
+            Body::Binary(data) => {
+                if data.len() < ty.size() {
+                    return Err("unexpected end of file".to_string());
+                }
+                let (x, rest) = data.split_at(ty.size());
+                *data = rest;
+                Ok(match ty {
+                    Scalar::I8 => x[0] as i8 as f64,
+                    Scalar::U8 => x[0] as f64,
+                    Scalar::I16 => i16::from_le_bytes([x[0], x[1]]) as f64,
+                    Scalar::U16 => u16::from_le_bytes([x[0], x[1]]) as f64,
+                    Scalar::I32 => i32::from_le_bytes([x[0], x[1], x[2], x[3]]) as f64,
+                    Scalar::U32 => u32::from_le_bytes([x[0], x[1], x[2], x[3]]) as f64,
+                    Scalar::F32 => f32::from_le_bytes([x[0], x[1], x[2], x[3]]) as f64,
+                    Scalar::F64 => f64::from_le_bytes(x.try_into().unwrap()),
+                })
+            },
+        }
+    }
+    /// a list count or vertex index, which has to be a whole number that isn't negative
+    fn read_index(&mut self, ty: Scalar) -> Result<usize, String> {
+        let x = self.read(ty)?;
+        if !x.is_finite() || x < 0.0 || x.fract() != 0.0 {
+            return Err(format!("{x} is not a valid count or index"));
+        }
+        Ok(x as usize)
+    }
+}
+
+/// loads the `vertex` and `face` elements of an ascii or binary little endian .ply file, other elements are skipped
+///
+/// vertices need `x`, `y` and `z`, and can have `nx`/`ny`/`nz` normals and `u`/`v` texture coordinates
+pub fn load_ply(path: &str) -> Result<IndexedMesh, MeshError> {
+    parse_ply(path, &read(path).map_err(|x| MeshError::Io(path.to_string(), x))?)
+}
+
+/// `path` is only for errors
+fn parse_ply(path: &str, data: &[u8]) -> Result<IndexedMesh, MeshError> {
+    let err = |x: String| MeshError::Format(path.to_string(), x);
+    let end = data.windows(10).position(|x| x == b"end_header").ok_or_else(|| err("missing end_header".to_string()))?;
+    // the data starts after the newline ending the header
+    let end = end + data[end..].iter().position(|&x| x == b'\n').map_or(data.len() - end, |x| x + 1);
+    let header = std::str::from_utf8(&data[..end]).map_err(|_| err("header is not text".to_string()))?;
+
+    let mut lines = header.lines();
+    if lines.next().map(str::trim) != Some("ply") {
+        return Err(err("not a ply file".to_string()));
+    }
+    let mut binary = None;
+    let mut elements: Vec<Element> = vec![];
+    for line in lines {
+        let words: Vec<&str> = line.split_ascii_whitespace().collect();
+        let scalar = |x: &str| Scalar::parse(x).ok_or_else(|| err(format!("unknown property type {x}")));
+        match words.as_slice() {
+            ["format", "ascii", _] => binary = Some(false),
+            ["format", "binary_little_endian", _] => binary = Some(true),
+            ["format", x, _] => return Err(err(format!("unsupported format {x}"))),
+            ["element", name, count] => {
+                let count = count.parse().map_err(|_| err(format!("bad element count {count:?}")))?;
+                elements.push(Element { name: name.to_string(), count, properties: vec![] });
+            },
+            ["property", rest @ ..] => {
+                let property = match rest {
+                    ["list", count, item, name] => Property::List(scalar(count)?, scalar(item)?, name.to_string()),
+                    [ty, name] => Property::Scalar(scalar(ty)?, name.to_string()),
+                    _ => return Err(err(format!("bad property {line:?}"))),
+                };
+                elements.last_mut().ok_or_else(|| err("property outside of an element".to_string()))?.properties.push(property);
+            },
+            ["comment" | "obj_info", ..] | ["end_header"] | [] => {},
+            _ => return Err(err(format!("unsupported header line {line:?}"))),
+        }
+    }
+    let mut body = match binary {
+        Some(true) => Body::Binary(&data[end..]),
+        Some(false) => Body::Ascii(std::str::from_utf8(&data[end..]).map_err(|_| err("ascii data is not text".to_string()))?.split_ascii_whitespace()),
+        None => return Err(err("missing format".to_string())),
+    };
+
+    let vertices = elements.iter().find(|x| x.name == "vertex").map_or(0, |x| x.count);
+    let mut mesh = IndexedMesh::default();
+    let (mut normals, mut texcoords) = (vec![], vec![]);
+    let mut polygons = vec![];
+    for element in &elements {
+        let position = [&["x"], &["y"], &["z"]].map(|x| element.find(x));
+        let normal = [&["nx"], &["ny"], &["nz"]].map(|x| element.find(x));
+        let texcoord = [&["u", "s", "texture_u"][..], &["v", "t", "texture_v"]].map(|x| element.find(x));
+        let indices = element.find(&["vertex_indices", "vertex_index"]);
+        if element.name == "vertex" && position.contains(&None) {
+            return Err(err("vertices need x, y and z".to_string()));
+        }
+
+        // lists are only kept for the face indices
+        let mut values = vec![0.0; element.properties.len()];
+        let mut list = vec![];
+        for _ in 0..element.count {
+            for (i, property) in element.properties.iter().enumerate() {
+                match *property {
+                    Property::Scalar(ty, _) => values[i] = body.read(ty).map_err(err)?,
+                    Property::List(count, item, _) => {
+                        let count = body.read_index(count).map_err(err)?;
+                        let face = Some(i) == indices && element.name == "face";
+                        list.clear();
+                        for _ in 0..count {
+                            if !face {
+                                body.read(item).map_err(err)?;
+                                continue;
+                            }
+                            let index = body.read_index(item).map_err(err)?;
+                            if index >= vertices {
+                                return Err(err(format!("index {index} out of range for {vertices} vertices")));
+                            }
+                            list.push(index);
+                        }
+                        if face {
+                            polygons.push(list.clone());
+                        }
+                    },
+                }
+            }
+            if element.name == "vertex" {
+                let get = |x: Option<usize>| x.map(|i| values[i] as f32);
+                mesh.positions.push(Vec3::from(position.map(|x| get(x).unwrap())));
+                if let [Some(x), Some(y), Some(z)] = normal.map(get) {
+                    normals.push(Vec3::new(x, y, z).normalized());
+                }
+                if let [Some(u), Some(v)] = texcoord.map(get) {
+                    texcoords.push(Vec2::new(u, v));
+                }
+            }
+        }
+    }
+
+    for polygon in polygons {
+        if polygon.len() < 3 {
+            return Err(err(format!("face with only {} vertices", polygon.len())));
+        }
+        let points: Vec<Vec3> = polygon.iter().map(|&i| mesh.positions[i]).collect();
+        mesh.faces.extend(triangulate(&points).into_iter().map(|t| t.map(|i| polygon[i])));
+    }
+    mesh.normals = (!normals.is_empty()).then_some(normals);
+    mesh.texcoords = (!texcoords.is_empty()).then_some(texcoords);
+    Ok(mesh)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const QUAD: &str = "ply\nformat ascii 1.0\nelement vertex 4\nproperty float x\nproperty float y\nproperty float z\nelement face 1\nproperty list uchar int vertex_indices\nend_header\n";
+
+    fn ascii(body: &str) -> Result<IndexedMesh, MeshError> {
+        parse_ply("test.ply", format!("{QUAD}{body}").as_bytes())
+    }
+
+    #[test]
+    fn ascii_quad() {
+        let mesh = ascii("0 0 0\n1 0 0\n1 1 0\n0 1 0\n4 0 1 2 3\n").unwrap();
+        assert_eq!(mesh.positions[2], Vec3::new(1.0, 1.0, 0.0));
+        assert_eq!(mesh.faces.len(), 2);
+        assert!(mesh.faces.iter().flatten().all(|&i| i < 4));
+        assert!(mesh.normals.is_none() && mesh.texcoords.is_none());
+    }
+
+    #[test]
+    fn binary_little_endian() {
+        let mut data = b"ply\nformat binary_little_endian 1.0\ncomment made by hand\nelement vertex 3\nproperty float x\nproperty float y\nproperty float z\nproperty float nx\nproperty float ny\nproperty float nz\nproperty float u\nproperty float v\nelement face 1\nproperty list uchar uint vertex_indices\nend_header\n".to_vec();
+        for v in [[0.0, 0.0, 0.0, 0.0, 0.0, 2.0, 0.0, 0.0], [1.0, 0.0, 0.0, 0.0, 0.0, 2.0, 1.0, 0.0], [0.0, 1.0, 0.0, 0.0, 0.0, 2.0, 0.0, 1.0f32]] {
+            v.iter().for_each(|x| data.extend(x.to_le_bytes()));
+        }
+        data.push(3);
+        [0u32, 1, 2].iter().for_each(|x| data.extend(x.to_le_bytes()));
+        let mesh = parse_ply("test.ply", &data).unwrap();
+        assert_eq!(mesh.positions, [Vec3::zero(), Vec3::unit_x(), Vec3::unit_y()]);
+        assert_eq!(mesh.faces, [[0, 1, 2]]);
+        // normals are normalized
+        assert_eq!(mesh.normals.unwrap()[1], Vec3::unit_z());
+        assert_eq!(mesh.texcoords.unwrap()[2], Vec2::new(0.0, 1.0));
+    }
+
+    #[test]
+    fn extra_properties_and_elements() {
+        let header = "ply\nformat ascii 1.0\nelement vertex 3\nproperty uchar red\nproperty float x\nproperty float y\nproperty list uchar float weights\nproperty float z\nelement face 1\nproperty int flags\nproperty list uchar int vertex_index\nelement edge 1\nproperty int vertex1\nproperty int vertex2\nend_header\n";
+        let body = "255 0 0 2 0.5 0.5 0\n0 1 0 0 0\n0 0 1 1 -2.5 0\n7 3 0 1 2\n0 1\n";
+        let mesh = parse_ply("test.ply", format!("{header}{body}").as_bytes()).unwrap();
+        assert_eq!(mesh.positions, [Vec3::zero(), Vec3::unit_x(), Vec3::unit_y()]);
+        assert_eq!(mesh.faces, [[0, 1, 2]]);
+    }
+
+    #[test]
+    fn bad_indices() {
+        let vertices = "0 0 0\n1 0 0\n1 1 0\n0 1 0\n";
+        for face in ["3 0 1 -1", "3 0 1 2.5", "3 0 1 4", "-3 0 1 2", "3 0 1 nan"] {
+            assert!(ascii(&format!("{vertices}{face}\n")).is_err(), "{face:?} was read");
+        }
+    }
+}
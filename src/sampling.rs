@@ -0,0 +1,193 @@
+use std::f32::consts::PI;
+
+use vek::{Vec2 as TVec2, Vec3 as TVec3};
+
+type Vec2 = TVec2<f32>;
+type Vec3 = TVec3<f32>;
+
+// the same as the functions of the same name in shader.comp, for the same uniform random numbers in [0, 1). only built
+// for the tests below, which check them against their distributions
+
+/// uniform over the unit disk, keeping nearby `u` close together
+pub fn concentric_disk(u: [f32; 2]) -> Vec2 {
+    let o = Vec2::from(u) * 2.0 - 1.0;
+    if o == Vec2::zero() {
+        return o;
+    }
+    let (r, theta) = if o.x.abs() > o.y.abs() { (o.x, PI / 4.0 * (o.y / o.x)) } else { (o.y, PI / 2.0 - PI / 4.0 * (o.x / o.y)) };
+    Vec2::new(theta.cos(), theta.sin()) * r
+}
+
+pub fn uniform_sphere(u: [f32; 2]) -> Vec3 {
+    let z = 1.0 - 2.0 * u[0];
+    let r = (1.0 - z * z).max(0.0).sqrt();
+    let phi = 2.0 * PI * u[1];
+    Vec3::new(r * phi.cos(), r * phi.sin(), z)
+}
+
+/// tangent, bitangent and `n` for a unit `n`, from Duff et al. 2017
+pub fn onb(n: Vec3) -> [Vec3; 3] {
+    let s = if n.z >= 0.0 { 1.0 } else { -1.0 };
+    let a = -1.0 / (s + n.z);
+    let b = n.x * n.y * a;
+    [Vec3::new(1.0 + s * n.x * n.x * a, s * b, -s * n.x), Vec3::new(b, s + n.y * n.y * a, -n.y), n]
+}
+
+/// around the unit normal `n`, by lifting a disk sample onto the hemisphere
+pub fn cosine_hemisphere(u: [f32; 2], n: Vec3) -> Vec3 {
+    let d = concentric_disk(u);
+    let z = (1.0 - d.magnitude_squared()).max(0.0).sqrt();
+    let [t, b, n] = onb(n);
+    t * d.x + b * d.y + n * z
+}
+
+pub fn cosine_hemisphere_pdf(n: Vec3, dir: Vec3) -> f32 {
+    n.dot(dir).max(0.0) / PI
+}
+
+/// alpha along x and y from a roughness and an anisotropy from -1 to 1
+pub fn ggx_alpha(roughness: f32, anisotropy: f32) -> Vec2 {
+    let a = (roughness * roughness).max(1e-4);
+    let aspect = (1.0 - 0.9 * anisotropy.abs()).sqrt();
+    if anisotropy >= 0.0 { Vec2::new(a / aspect, a * aspect) } else { Vec2::new(a * aspect, a / aspect) }
+}
+
+/// ggx normal distribution in a local frame where the normal is +z
+pub fn ggx_d(h: Vec3, alpha: Vec2) -> f32 {
+    let t = h.x * h.x / (alpha.x * alpha.x) + h.y * h.y / (alpha.y * alpha.y) + h.z * h.z;
+    1.0 / (PI * alpha.x * alpha.y * t * t)
+}
+
+pub fn ggx_lambda(w: Vec3, alpha: Vec2) -> f32 {
+    ((1.0 + (alpha.x * alpha.x * w.x * w.x + alpha.y * alpha.y * w.y * w.y) / (w.z * w.z)).sqrt() - 1.0) / 2.0
+}
+
+/// a normal visible from `wo`, distributed by `ggx_vndf`, from Heitz 2018
+pub fn sample_vndf(wo: Vec3, alpha: Vec2, u: [f32; 2]) -> Vec3 {
+    let v = Vec3::new(alpha.x * wo.x, alpha.y * wo.y, wo.z).normalized();
+    let len2 = v.x * v.x + v.y * v.y;
+    let t1 = if len2 > 0.0 { Vec3::new(-v.y, v.x, 0.0) / len2.sqrt() } else { Vec3::unit_x() };
+    let t2 = v.cross(t1);
+    let (r, phi) = (u[0].sqrt(), 2.0 * PI * u[1]);
+    let p1 = r * phi.cos();
+    let s = 0.5 * (1.0 + v.z);
+    let p2 = (1.0 - s) * (1.0 - p1 * p1).sqrt() + s * r * phi.sin();
+    let n = t1 * p1 + t2 * p2 + v * (1.0 - p1 * p1 - p2 * p2).max(0.0).sqrt();
+    Vec3::new(alpha.x * n.x, alpha.y * n.y, n.z.max(0.0)).normalized()
+}
+
+/// density of the normals visible from `wo`
+pub fn ggx_vndf(wo: Vec3, h: Vec3, alpha: Vec2) -> f32 {
+    wo.dot(h).max(0.0) * ggx_d(h, alpha) / (wo.z * (1.0 + ggx_lambda(wo, alpha)))
+}
+
+/// solid angle pdf of reflecting `wo` about a normal from `sample_vndf`
+pub fn vndf_reflect_pdf(wo: Vec3, h: Vec3, alpha: Vec2) -> f32 {
+    ggx_d(h, alpha) / (4.0 * wo.z * (1.0 + ggx_lambda(wo, alpha)))
+}
+
+#[cfg(test)]
+mod tests {
+    use rand::{rngs::StdRng, Rng, SeedableRng};
+
+    use super::*;
+
+    const SAMPLES: usize = 100_000;
+    const SIZE: usize = 16;
+
+    /// chi-square test of `bins` against equal expected counts, with a threshold 5 standard deviations above the mean
+    fn chi_square(name: &str, bins: &[u32], samples: usize) {
+        let expected = samples as f32 / bins.len() as f32;
+        let chi2: f32 = bins.iter().map(|&x| (x as f32 - expected).powi(2) / expected).sum();
+        let dof = (bins.len() - 1) as f32;
+        assert!(chi2 < dof + 5.0 * (2.0 * dof).sqrt(), "{name} failed a chi-square test: {chi2} with {dof} degrees of freedom");
+    }
+
+    /// bins for two values in [0, 1], sized so that each has the same probability
+    fn bin(bins: &mut [u32], x: f32, y: f32) {
+        let i = |x: f32| ((x * SIZE as f32) as usize).min(SIZE - 1);
+        bins[i(y) * SIZE + i(x)] += 1;
+    }
+
+    fn angle(x: f32, y: f32) -> f32 {
+        y.atan2(x) / (2.0 * PI) + 0.5
+    }
+
+    #[test]
+    fn concentric_disk_is_uniform() {
+        let mut rng = StdRng::seed_from_u64(1);
+        let mut bins = [0; SIZE * SIZE];
+        for _ in 0..SAMPLES {
+            let p = concentric_disk(rng.gen());
+            assert!(p.magnitude_squared() <= 1.0 + 1e-5, "concentric_disk returned {p} outside the unit disk");
+            bin(&mut bins, p.magnitude_squared(), angle(p.x, p.y));
+        }
+        chi_square("concentric_disk", &bins, SAMPLES);
+    }
+
+    #[test]
+    fn uniform_sphere_is_uniform() {
+        let mut rng = StdRng::seed_from_u64(2);
+        let mut bins = [0; SIZE * SIZE];
+        for _ in 0..SAMPLES {
+            let dir = uniform_sphere(rng.gen());
+            assert!((dir.magnitude() - 1.0).abs() < 1e-4, "uniform_sphere returned {dir} which is not normalized");
+            // the area between two heights of a sphere is proportional to their distance
+            bin(&mut bins, (dir.z + 1.0) / 2.0, angle(dir.x, dir.y));
+        }
+        chi_square("uniform_sphere", &bins, SAMPLES);
+    }
+
+    #[test]
+    fn cosine_hemisphere_matches_its_pdf() {
+        let mut rng = StdRng::seed_from_u64(3);
+        let mut bins = [0; SIZE * SIZE];
+        let mut integral = 0.0;
+        for _ in 0..SAMPLES {
+            let n = uniform_sphere(rng.gen());
+            let dir = cosine_hemisphere(rng.gen(), n);
+            assert!((dir.magnitude() - 1.0).abs() < 1e-4 && n.dot(dir) >= -1e-4, "cosine_hemisphere returned {dir} for {n}");
+            let [t, b, n] = onb(n);
+            // sin^2 of the angle to the normal is uniform for a cosine distribution
+            bin(&mut bins, 1.0 - n.dot(dir).powi(2), angle(t.dot(dir), b.dot(dir)));
+            integral += n.dot(dir).powi(2) / cosine_hemisphere_pdf(n, dir).max(1e-6);
+        }
+        chi_square("cosine_hemisphere", &bins, SAMPLES);
+        // cos^2 over the hemisphere integrates to 2pi/3
+        let integral = integral / SAMPLES as f32;
+        assert!((integral - 2.0 * PI / 3.0).abs() < 0.02, "cosine_hemisphere_pdf estimates the integral of cos^2 as {integral}");
+    }
+
+    #[test]
+    fn ggx_distributions_are_normalized() {
+        let mut rng = StdRng::seed_from_u64(4);
+        for (roughness, anisotropy, wo) in [(0.8, 0.0, Vec3::unit_z()), (0.9, 0.5, Vec3::new(0.6, 0.3, 0.5)), (0.8, -0.6, Vec3::new(-0.2, 0.9, 0.1))] {
+            let (alpha, wo) = (ggx_alpha(roughness, anisotropy), wo.normalized());
+            // integrals over the sphere by uniform sampling, the projected normal distribution and the visible normal
+            // distribution both integrate to 1, the mean visible normal has to match the sampled one, and the reflection
+            // pdf has to integrate to how often reflected samples stay above the surface
+            let (mut d, mut vndf, mut reflect, mut mean) = (0.0, 0.0, 0.0, Vec3::zero());
+            for _ in 0..SAMPLES {
+                let w = uniform_sphere(rng.gen());
+                if w.z > 0.0 {
+                    d += ggx_d(w, alpha) * w.z;
+                    vndf += ggx_vndf(wo, w, alpha);
+                    mean += w * ggx_vndf(wo, w, alpha);
+                    reflect += vndf_reflect_pdf(wo, (wo + w).normalized(), alpha);
+                }
+            }
+            let [d, vndf, reflect] = [d, vndf, reflect].map(|x| x * 4.0 * PI / SAMPLES as f32);
+            let mean = mean * 4.0 * PI / SAMPLES as f32;
+            let (mut sampled, mut above) = (Vec3::zero(), 0);
+            for _ in 0..SAMPLES {
+                let h = sample_vndf(wo, alpha, rng.gen());
+                sampled += h / SAMPLES as f32;
+                above += (2.0 * wo.dot(h) * h - wo).z.is_sign_positive() as usize;
+            }
+            let above = above as f32 / SAMPLES as f32;
+            assert!((d - 1.0).abs() < 0.03 && (vndf - 1.0).abs() < 0.03, "ggx distributions integrate to {d} and {vndf} for {alpha} and {wo}");
+            assert!(mean.distance(sampled) < 0.03, "sample_vndf has a mean of {sampled} instead of {mean} for {alpha} and {wo}");
+            assert!((reflect - above).abs() < 0.03, "vndf_reflect_pdf integrates to {reflect} but {above} of the reflections stay above the surface");
+        }
+    }
+}
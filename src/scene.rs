@@ -0,0 +1,294 @@
+use std::{collections::HashMap, fmt::Display, fs::read_to_string};
+
+use serde::Deserialize;
+use vek::{Mat4, Vec3};
+
+use crate::{bvh::{Bvh, TRI_BIT}, environment::EnvMap, material::{GpuMaterial, Material, TexturedMaterial}, mesh::{load_mesh, MeshError}, obj::Tri, texture::Textures};
+
+#[derive(Deserialize, Clone, Copy)]
+pub struct Camera {
+    pub lookfrom: [f32; 3],
+    pub lookat: [f32; 3],
+    pub vfov: f32,
+    /// up in the image, only the part of it across the view direction matters
+    #[serde(default = "up")]
+    pub vup: [f32; 3],
+}
+
+fn up() -> [f32; 3] {
+    [0.0, 1.0, 0.0]
+}
+
+#[derive(Deserialize)]
+#[serde(default)]
+pub struct Render {
+    pub width: usize,
+    pub height: usize,
+    pub samples: usize,
+    pub output: String,
+    /// bounces before paths can be ended early by russian roulette
+    pub min_depth: u32,
+    pub max_depth: u32,
+}
+impl Default for Render {
+    fn default() -> Self {
+        Self { width: 1920, height: 1200, samples: 100, output: "img.png".to_string(), min_depth: 3, max_depth: 100 }
+    }
+}
+
+#[derive(Deserialize)]
+pub struct Environment {
+    /// equirectangular .hdr or .exr
+    pub path: String,
+    /// around the y axis in degrees
+    #[serde(default)]
+    pub rotation: f32,
+    #[serde(default = "one")]
+    pub intensity: f32,
+}
+
+#[derive(Deserialize, Clone, Copy, PartialEq)]
+#[serde(rename_all = "snake_case")]
+#[repr(u32)]
+pub enum SkyModel {
+    /// blends from `horizon` straight down to `zenith` straight up, with a glow around the sun
+    Gradient,
+    /// the Preetham et al. daylight model for the sun direction and `turbidity`, scaled to a zenith luminance of 1
+    Preetham,
+}
+
+/// the procedural sky used without an environment map
+#[derive(Deserialize)]
+#[serde(default)]
+pub struct Sky {
+    pub model: SkyModel,
+    /// towards the sun
+    pub sun_direction: [f32; 3],
+    /// angular diameter in degrees
+    pub sun_size: f32,
+    pub sun_color: [f32; 3],
+    pub sun_intensity: f32,
+    pub zenith: [f32; 3],
+    pub horizon: [f32; 3],
+    /// haziness for the preetham model, from 2 for a clear sky to 10
+    pub turbidity: f32,
+}
+impl Default for Sky {
+    fn default() -> Self {
+        Self { model: SkyModel::Gradient, sun_direction: [-1.0, 1.0, -1.0], sun_size: 11.5, sun_color: [0.98, 0.99, 0.62], sun_intensity: 1.7, zenith: [0.5, 0.7, 1.0], horizon: [1.0; 3], turbidity: 3.0 }
+    }
+}
+
+#[derive(Deserialize)]
+pub struct Sphere {
+    pub center: [f32; 3],
+    pub radius: f32,
+    pub material: String,
+}
+
+#[derive(Deserialize)]
+pub struct Mesh {
+    pub path: String,
+    /// overrides the materials from the mesh's mtl libraries
+    pub material: Option<String>,
+    #[serde(default)]
+    pub pos: [f32; 3],
+    /// euler angles in degrees
+    #[serde(default)]
+    pub rot: [f32; 3],
+    #[serde(default = "one")]
+    pub scale: f32,
+    /// smooth normals of faces without `vn` across edges below this angle in degrees, flat if not set
+    pub smooth_angle: Option<f32>,
+    /// use the first camera of a .gltf/.glb mesh instead of `[camera]`
+    #[serde(default)]
+    pub camera: bool,
+    /// reuse the parsed mesh from a `.rtcache` file next to it, which is written if missing or outdated
+    #[serde(default = "yes")]
+    pub cache: bool,
+    /// only instance this `o`/`g` part of the mesh
+    pub part: Option<String>,
+    /// overrides for `o`/`g` parts by name, `"object"` matches all of its groups and `"object/group"` just the one
+    #[serde(default)]
+    pub parts: HashMap<String, Part>,
+}
+
+#[derive(Deserialize)]
+pub struct Part {
+    #[serde(default)]
+    pub hidden: bool,
+    /// overrides both the mesh material and the mtl materials
+    pub material: Option<String>,
+    /// applied in mesh space before the mesh transform
+    #[serde(default)]
+    pub pos: [f32; 3],
+    /// euler angles in degrees
+    #[serde(default)]
+    pub rot: [f32; 3],
+    #[serde(default = "one")]
+    pub scale: f32,
+}
+impl Part {
+    fn matrix(&self) -> Mat4<f32> {
+        let rot = Vec3::from(self.rot).map(f32::to_radians);
+        Mat4::<f32>::translation_3d(self.pos) * Mat4::scaling_3d(Vec3::broadcast(self.scale)) * Mat4::rotation_z(rot.z) * Mat4::rotation_y(rot.y) * Mat4::rotation_x(rot.x)
+    }
+}
+/// `key` names either the whole group or the object it is in
+fn part_matches(key: &str, group: &str) -> bool {
+    group == key || group.strip_prefix(key).is_some_and(|x| x.starts_with('/'))
+}
+fn one() -> f32 {
+    1.0
+}
+fn yes() -> bool {
+    true
+}
+
+#[derive(Deserialize)]
+pub struct Scene {
+    /// only optional if a mesh provides the camera
+    pub camera: Option<Camera>,
+    #[serde(default)]
+    pub render: Render,
+    /// lights rays that miss everything, the procedural sky is used without one
+    pub environment: Option<Environment>,
+    #[serde(default)]
+    pub sky: Sky,
+    #[serde(default)]
+    pub materials: HashMap<String, TexturedMaterial>,
+    #[serde(default)]
+    pub spheres: Vec<Sphere>,
+    #[serde(default)]
+    pub meshes: Vec<Mesh>,
+}
+impl Scene {
+    pub fn load(path: &str) -> Result<Self, SceneError> {
+        let file = read_to_string(path).map_err(|x| SceneError::Io(path.to_string(), x))?;
+        let scene: Self = toml::from_str(&file).map_err(SceneError::Parse)?;
+        if let Some((name, _)) = scene.materials.iter().find(|(_, x)| !x.material.is_valid()) {
+            return Err(SceneError::InvalidMaterial(name.clone()));
+        }
+        Ok(scene)
+    }
+    fn material(&self, name: &str) -> Result<&TexturedMaterial, SceneError> {
+        self.materials.get(name).ok_or_else(|| SceneError::UnknownMaterial(name.to_string()))
+    }
+    /// loads every mesh and flattens the scene into the buffers the shader reads
+    pub fn build(&self) -> Result<SceneData, SceneError> {
+        let spheres = self.spheres.iter().map(|x| [x.center[0], x.center[1], x.center[2], x.radius]).collect();
+        let mut textures = Textures::default();
+        let mut gpu = |x: &TexturedMaterial| -> Result<GpuMaterial, SceneError> {
+            let albedo = x.albedo_map.as_ref().map_or(Ok(-1), |path| textures.load(path).map_err(|x| SceneError::Texture(path.clone(), x)))?;
+            let [data, params, extra @ ..] = x.material.encode();
+            Ok(GpuMaterial { data, params, extra, maps: [albedo, -1, -1, -1] })
+        };
+        let mut materials = self.spheres.iter().map(|x| gpu(self.material(&x.material)?)).collect::<Result<Vec<_>, _>>()?;
+        let mut tris = Vec::new();
+        let mut subtrees = vec![];
+        let mut camera = None;
+        for mesh in &self.meshes {
+            let (pos, rot, crease_angle) = (mesh.pos.into(), Vec3::from(mesh.rot).map(f32::to_radians), mesh.smooth_angle.map(f32::to_radians));
+            let loaded = load_mesh(&mesh.path, pos, rot, mesh.scale, crease_angle, mesh.cache).map_err(SceneError::Mesh)?;
+            let (obj, mesh_camera) = (loaded.obj, loaded.camera);
+            if mesh.camera && camera.is_none() {
+                camera = Some(mesh_camera.ok_or_else(|| SceneError::MissingCamera(Some(mesh.path.clone())))?);
+            }
+            if let Some(key) = mesh.parts.keys().chain(&mesh.part).find(|k| !obj.groups.iter().any(|g| part_matches(k, &g.name))) {
+                return Err(SceneError::UnknownPart(mesh.path.clone(), key.clone()));
+            }
+            let offset = materials.len() as u32;
+            // the scene material replaces the mtl materials if given
+            match &mesh.material {
+                Some(name) => materials.push(gpu(self.material(name)?)?),
+                None => materials.extend(obj.materials.iter().map(&mut gpu).collect::<Result<Vec<_>, _>>()?),
+            }
+            let model = obj.get_model_world_matrix();
+            let inverse = model.inverted();
+            let start = tris.len();
+            for group in &obj.groups {
+                if mesh.part.as_ref().is_some_and(|x| !part_matches(x, &group.name)) {
+                    continue;
+                }
+                // a group override wins over its object's
+                let part = mesh.parts.iter().filter(|(k, _)| part_matches(k, &group.name)).max_by_key(|(k, _)| k.len()).map(|x| x.1);
+                if part.is_some_and(|x| x.hidden) {
+                    continue;
+                }
+                let mat = match part.and_then(|x| x.material.as_ref()) {
+                    Some(name) => {
+                        materials.push(gpu(self.material(name)?)?);
+                        Some(materials.len() as u32 - 1)
+                    },
+                    None if mesh.material.is_some() => Some(offset),
+                    None => None,
+                };
+                let transform = part.map_or(Mat4::identity(), |x| model * x.matrix() * inverse);
+                let normal = transform.inverted().transposed();
+                tris.extend(obj.tris[group.tris.clone()].iter().map(|x| {
+                    let mut x = x.transformed(transform, normal);
+                    x.mat = mat.unwrap_or(offset + x.mat);
+                    x
+                }));
+            }
+            // the mesh bvh only matches if no part was left out
+            if tris.len() - start == obj.tris.len() {
+                subtrees.push((start, loaded.bvh));
+            }
+        }
+        let camera = camera.or(self.camera).ok_or(SceneError::MissingCamera(None))?;
+        let environment = self.environment.as_ref().map(|x| EnvMap::load(&x.path).map_err(|err| SceneError::Texture(x.path.clone(), err))).transpose()?;
+        // black lights would only waste shadow rays
+        let emissive = |i: u32| {
+            let x = &materials[i as usize];
+            Material::decode([x.data, x.params, x.extra[0], x.extra[1]]).is_some_and(|x| x.emission() != [0.0; 3])
+        };
+        let lights = (0..self.spheres.len() as u32).filter(|&i| emissive(i)).chain((0..tris.len() as u32).filter(|&i| emissive(tris[i as usize].mat)).map(|i| i | TRI_BIT)).collect();
+        Ok(SceneData { camera, spheres, materials, textures, environment, tris, subtrees, lights })
+    }
+}
+
+/// gpu ready scene contents
+pub struct SceneData {
+    pub camera: Camera,
+    /// `vec4(center, r)`
+    pub spheres: Vec<[f32; 4]>,
+    /// sphere i uses material i and triangles store their own index
+    pub materials: Vec<GpuMaterial>,
+    pub textures: Textures,
+    pub environment: Option<EnvMap>,
+    pub tris: Vec<Tri>,
+    /// model space bvhs of whole meshes and where their tris start, for `Bvh::build`
+    pub subtrees: Vec<(usize, Bvh)>,
+    /// emissive spheres and triangles, encoded like `Bvh::prims`
+    pub lights: Vec<u32>,
+}
+
+#[derive(Debug)]
+pub enum SceneError {
+    Io(String, std::io::Error),
+    Parse(toml::de::Error),
+    UnknownMaterial(String),
+    InvalidMaterial(String),
+    /// mesh path and part name
+    UnknownPart(String, String),
+    Mesh(MeshError),
+    Texture(String, image::ImageError),
+    /// the mesh that was supposed to have a camera, if any
+    MissingCamera(Option<String>),
+}
+impl Display for SceneError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            SceneError::Io(path, err) => write!(f, "failed to read scene {path}: {err}"),
+            SceneError::Parse(err) => write!(f, "failed to parse scene: {err}"),
+            SceneError::UnknownMaterial(name) => write!(f, "unknown material {name:?}"),
+            SceneError::InvalidMaterial(name) => write!(f, "material {name:?} cannot be represented by the shader"),
+            SceneError::UnknownPart(path, name) => write!(f, "mesh {path} has no part {name:?}"),
+            SceneError::Mesh(err) => write!(f, "failed to load mesh {err}"),
+            SceneError::Texture(path, err) => write!(f, "failed to load texture {path}: {err}"),
+            SceneError::MissingCamera(Some(path)) => write!(f, "mesh {path} has no perspective camera"),
+            SceneError::MissingCamera(None) => write!(f, "scene has no camera"),
+        }
+    }
+}
@@ -0,0 +1,275 @@
+use std::f32::consts::PI;
+
+use vek::Vec3 as TVec3;
+
+use crate::{environment::EnvMap, scene::{Sky, SkyModel}};
+
+type Vec3 = TVec3<f32>;
+
+/// size of the table for the procedural sky
+const PROCEDURAL_SIZE: [u32; 2] = [512, 256];
+/// larger environment maps are averaged down to this
+const MAX_SIZE: [u32; 2] = [1024, 512];
+
+/// the procedural sky as the shader reads it from the ubo, `radiance` is the same as `sky` in shader.comp without an
+/// environment map
+pub struct ProceduralSky {
+    pub model: SkyModel,
+    pub sun_dir: Vec3,
+    /// of the sun's angular radius
+    pub sun_cos: f32,
+    pub sun_radiance: Vec3,
+    pub zenith: Vec3,
+    pub horizon: Vec3,
+    /// perez coefficients A to E for Y, x and y
+    pub perez: [Vec3; 5],
+    /// zenith Yxy over the perez function at the zenith, times the perez function of a direction is its Yxy
+    pub zenith_yxy: Vec3,
+}
+impl ProceduralSky {
+    pub fn new(sky: &Sky) -> Self {
+        let t = sky.turbidity;
+        let perez = [
+            Vec3::new(0.1787 * t - 1.4630, -0.0193 * t - 0.2592, -0.0167 * t - 0.2608),
+            Vec3::new(-0.3554 * t + 0.4275, -0.0665 * t + 0.0008, -0.0950 * t + 0.0092),
+            Vec3::new(-0.0227 * t + 5.3251, -0.0004 * t + 0.2125, -0.0079 * t + 0.2102),
+            Vec3::new(0.1206 * t - 2.5771, -0.0641 * t - 0.8989, -0.0441 * t - 1.6537),
+            Vec3::new(-0.0670 * t + 0.3703, -0.0033 * t + 0.0452, -0.0109 * t + 0.0529),
+        ];
+        let sun_dir = Vec3::from(sky.sun_direction).normalized();
+        let mut out = Self {
+            model: sky.model,
+            sun_dir,
+            sun_cos: (sky.sun_size.to_radians() / 2.0).cos(),
+            sun_radiance: Vec3::from(sky.sun_color) * sky.sun_intensity,
+            zenith: sky.zenith.into(),
+            horizon: sky.horizon.into(),
+            perez,
+            zenith_yxy: Vec3::one(),
+        };
+        // the fits only cover the sun above the horizon
+        let theta = sun_dir.y.clamp(0.0, 1.0).acos();
+        let [th, th2, th3] = [theta, theta * theta, theta * theta * theta];
+        let x = t * t * (0.00166 * th3 - 0.00375 * th2 + 0.00209 * th) + t * (-0.02903 * th3 + 0.06377 * th2 - 0.03202 * th + 0.00394) + 0.11693 * th3 - 0.21196 * th2 + 0.06052 * th + 0.25886;
+        let y = t * t * (0.00275 * th3 - 0.00610 * th2 + 0.00317 * th) + t * (-0.04214 * th3 + 0.08970 * th2 - 0.04153 * th + 0.00516) + 0.15346 * th3 - 0.26756 * th2 + 0.06670 * th + 0.26688;
+        // the luminance is scaled to 1 at the zenith, so the absolute zenith luminance isn't needed
+        out.zenith_yxy = Vec3::new(1.0, x, y) / out.perez(1.0, theta);
+        out
+    }
+    fn perez(&self, cos_theta: f32, gamma: f32) -> Vec3 {
+        let [a, b, c, d, e] = self.perez;
+        (Vec3::one() + a * (b / cos_theta).map(f32::exp)) * (Vec3::one() + c * (d * gamma).map(f32::exp) + e * gamma.cos() * gamma.cos())
+    }
+    pub fn radiance(&self, dir: Vec3) -> Vec3 {
+        let cos_sun = self.sun_dir.dot(dir);
+        if cos_sun > self.sun_cos {
+            return self.sun_radiance;
+        }
+        match self.model {
+            SkyModel::Gradient => {
+                // fades out at twice the sun's 1 - cos
+                let glow = 1.0 - (1.0 - cos_sun) / (2.0 * (1.0 - self.sun_cos));
+                Vec3::lerp(Vec3::lerp(self.horizon, self.zenith, 0.5 * (dir.y + 1.0)), self.sun_radiance, glow.clamp(0.0, 1.0))
+            },
+            SkyModel::Preetham => {
+                // everything below the horizon looks like the horizon
+                let [big_y, x, y] = (self.zenith_yxy * self.perez(dir.y.max(1e-3), cos_sun.clamp(-1.0, 1.0).acos())).into_array();
+                let [big_x, big_z] = [x * big_y / y, (1.0 - x - y) * big_y / y];
+                Vec3::new(
+                    3.2406 * big_x - 1.5372 * big_y - 0.4986 * big_z,
+                    -0.9689 * big_x + 1.8758 * big_y + 0.0415 * big_z,
+                    0.0557 * big_x - 0.2040 * big_y + 1.0570 * big_z,
+                ).map(|x| x.max(0.0))
+            },
+        }
+    }
+}
+
+/// direction of an equirectangular map coordinate, `v` is 0 straight up and the middle of the map faces -z before
+/// rotating around y by `rotation` radians
+pub fn direction(u: f32, v: f32, rotation: f32) -> Vec3 {
+    let phi = (u - 0.5) * 2.0 * PI + rotation;
+    let theta = v * PI;
+    Vec3::new(theta.sin() * phi.sin(), theta.cos(), -theta.sin() * phi.cos())
+}
+
+/// inverse of `direction`
+pub fn map_coords(dir: Vec3, rotation: f32) -> (f32, f32) {
+    let phi = dir.x.atan2(-dir.z) - rotation;
+    ((phi / (2.0 * PI) + 0.5).rem_euclid(1.0), dir.y.clamp(-1.0, 1.0).acos() / PI)
+}
+
+fn luminance(x: Vec3) -> f32 {
+    0.2126 * x.x + 0.7152 * x.y + 0.0722 * x.z
+}
+
+/// piecewise constant distribution over the sky for importance sampling it, the same as `sample_sky` and `sky_pdf` in
+/// shader.comp
+pub struct SkyDistribution {
+    pub width: u32,
+    pub height: u32,
+    /// a cumulative distribution over each row, then one over the rows. each ends at 1
+    pub cdf: Vec<f32>,
+}
+impl SkyDistribution {
+    /// over the environment map, or the procedural sky without one
+    pub fn build(map: Option<&EnvMap>, sky: &ProceduralSky) -> Option<Self> {
+        match map {
+            Some(map) => {
+                let [width, height] = [map.width.min(MAX_SIZE[0]), map.height.min(MAX_SIZE[1])];
+                let mut cells = vec![(0.0, 0); (width * height) as usize];
+                for (i, x) in map.texels.iter().enumerate() {
+                    let (col, row) = (i as u32 % map.width * width / map.width, i as u32 / map.width * height / map.height);
+                    let cell = &mut cells[(row * width + col) as usize];
+                    *cell = (cell.0 + luminance(Vec3::new(x[0], x[1], x[2])), cell.1 + 1);
+                }
+                Self::new(width, height, cells.into_iter().map(|(sum, count)| sum / count.max(1) as f32).collect())
+            },
+            None => {
+                let [width, height] = PROCEDURAL_SIZE;
+                let mut cells: Vec<f32> = (0..width * height).map(|i| {
+                    let (u, v) = ((i % width) as f32 + 0.5, (i / width) as f32 + 0.5);
+                    luminance(sky.radiance(direction(u / width as f32, v / height as f32, 0.0)))
+                }).collect();
+                // a sun smaller than a cell can fall between the cell centers, so give its cell at least the sun's power
+                let (u, v) = map_coords(sky.sun_dir, 0.0);
+                let (col, row) = (((u * width as f32) as u32).min(width - 1), ((v * height as f32) as u32).min(height - 1));
+                let cell_angle = (2.0 * PI / width as f32) * (PI / height as f32) * ((row as f32 + 0.5) / height as f32 * PI).sin();
+                let sun = luminance(sky.sun_radiance) * 2.0 * PI * (1.0 - sky.sun_cos) / cell_angle;
+                let cell = &mut cells[(row * width + col) as usize];
+                *cell = cell.max(sun);
+                Self::new(width, height, cells)
+            },
+        }
+    }
+    /// `luminance` of each cell, rows from the top. `None` if the sky is black
+    fn new(width: u32, height: u32, luminance: Vec<f32>) -> Option<Self> {
+        // cells near the poles cover less solid angle
+        let weights: Vec<f32> = luminance.iter().enumerate().map(|(i, &x)| {
+            let theta = ((i as u32 / width) as f32 + 0.5) / height as f32 * PI;
+            x.max(0.0) * theta.sin()
+        }).collect();
+        let total: f32 = weights.iter().sum();
+        if total <= 0.0 || !total.is_finite() {
+            return None;
+        }
+        // keep every direction possible so MIS never divides by a zero pdf
+        let floor = total / weights.len() as f32 * 1e-3;
+
+        let mut cdf = Vec::with_capacity(((width + 1) * height) as usize);
+        let mut rows = Vec::with_capacity(height as usize);
+        for row in weights.chunks_exact(width as usize) {
+            let sum: f32 = row.iter().map(|x| x + floor).sum();
+            let mut acc = 0.0;
+            cdf.extend(row.iter().map(|x| {
+                acc += x + floor;
+                acc / sum
+            }));
+            *cdf.last_mut().unwrap() = 1.0;
+            rows.push(sum);
+        }
+        let sum: f32 = rows.iter().sum();
+        let mut acc = 0.0;
+        cdf.extend(rows.iter().map(|x| {
+            acc += x;
+            acc / sum
+        }));
+        *cdf.last_mut().unwrap() = 1.0;
+        Some(Self { width, height, cdf })
+    }
+    #[cfg(test)]
+    /// probability of `cdf[start + i]`
+    fn step(&self, start: usize, i: usize) -> f32 {
+        self.cdf[start + i] - if i == 0 { 0.0 } else { self.cdf[start + i - 1] }
+    }
+    #[cfg(test)]
+    /// first index in `cdf[start..start + count]` above `x`
+    fn search(&self, start: usize, count: usize, x: f32) -> usize {
+        self.cdf[start..start + count].partition_point(|&c| c <= x).min(count - 1)
+    }
+    #[cfg(test)]
+    /// direction and its solid angle pdf for two uniform random numbers
+    fn sample(&self, r: [f32; 2], rotation: f32) -> (Vec3, f32) {
+        let (width, height) = (self.width as usize, self.height as usize);
+        let marginal = width * height;
+        let row = self.search(marginal, height, r[1]);
+        let col = self.search(row * width, width, r[0]);
+        // reuse where the random numbers fell in the cell to place the sample inside it
+        let below = |start: usize, i: usize| if i == 0 { 0.0 } else { self.cdf[start + i - 1] };
+        let fy = ((r[1] - below(marginal, row)) / self.step(marginal, row)).clamp(0.0, 1.0);
+        let fx = ((r[0] - below(row * width, col)) / self.step(row * width, col)).clamp(0.0, 1.0);
+        let (u, v) = ((col as f32 + fx) / width as f32, (row as f32 + fy) / height as f32);
+        (direction(u, v, rotation), self.cell_pdf(col, row, v))
+    }
+    #[cfg(test)]
+    /// solid angle pdf of `sample` returning `dir`
+    fn pdf(&self, dir: Vec3, rotation: f32) -> f32 {
+        let (u, v) = map_coords(dir, rotation);
+        let col = ((u * self.width as f32) as usize).min(self.width as usize - 1);
+        let row = ((v * self.height as f32) as usize).min(self.height as usize - 1);
+        self.cell_pdf(col, row, v)
+    }
+    #[cfg(test)]
+    fn cell_pdf(&self, col: usize, row: usize, v: f32) -> f32 {
+        let sin_theta = (v * PI).sin();
+        if sin_theta <= 0.0 {
+            return 0.0;
+        }
+        let p = self.step((self.width * self.height) as usize, row) * self.step(row * self.width as usize, col);
+        p * (self.width * self.height) as f32 / (2.0 * PI * PI * sin_theta)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rand::{rngs::StdRng, Rng, SeedableRng};
+
+    use super::*;
+
+    /// asserts that `sample` and `pdf` agree and that the pdf integrates to 1 over the sphere
+    fn check(sky: &SkyDistribution, samples: usize) {
+        let mut rng = StdRng::seed_from_u64(1);
+        for _ in 0..samples {
+            let (dir, pdf) = sky.sample(rng.gen(), 0.3);
+            // samples right on a cell edge can round into the neighbouring cell, and the longitude is too imprecise at
+            // the poles to find the same cell
+            let (u, v) = map_coords(dir, 0.3);
+            let edge = |x: f32| (x - x.round()).abs() < 1e-3;
+            if dir.y.abs() > 0.9999 || edge(u * sky.width as f32) || edge(v * sky.height as f32) {
+                continue;
+            }
+            let lookup = sky.pdf(dir, 0.3);
+            assert!((pdf - lookup).abs() <= 1e-2 * pdf.max(1.0), "sky sample pdf {pdf} but lookup {lookup} for {dir}");
+        }
+        // midpoint rule over the cells
+        let (width, height) = (sky.width as f32, sky.height as f32);
+        let integral: f32 = (0..sky.width * sky.height).map(|i| {
+            let (u, v) = (((i % sky.width) as f32 + 0.5) / width, ((i / sky.width) as f32 + 0.5) / height);
+            sky.pdf(direction(u, v, 0.3), 0.3) * (2.0 * PI / width) * (PI / height) * (v * PI).sin()
+        }).sum();
+        assert!((integral - 1.0).abs() < 1e-2, "sky pdf integrates to {integral}");
+    }
+
+    #[test]
+    fn procedural_skies() {
+        for model in [SkyModel::Gradient, SkyModel::Preetham] {
+            let sky = ProceduralSky::new(&Sky { model, ..Default::default() });
+            check(&SkyDistribution::build(None, &sky).unwrap(), 10_000);
+        }
+    }
+
+    #[test]
+    fn environment_map() {
+        // a dim gradient with a small bright spot, like a sun in an hdr
+        let (width, height) = (64, 32);
+        let texels = (0..width * height).map(|i| {
+            let x = if i % width == 40 && i / width == 10 { 1000.0 } else { (i / width) as f32 / height as f32 };
+            [x, x, x, 0.0]
+        }).collect();
+        let map = EnvMap { width, height, texels };
+        let sky = ProceduralSky::new(&Sky::default());
+        check(&SkyDistribution::build(Some(&map), &sky).unwrap(), 10_000);
+        let black = EnvMap { width, height, texels: vec![[0.0; 4]; (width * height) as usize] };
+        assert!(SkyDistribution::build(Some(&black), &sky).is_none());
+    }
+}
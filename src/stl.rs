@@ -0,0 +1,140 @@
+use std::{collections::HashMap, fs::read};
+
+use vek::Vec3 as TVec3;
+
+use crate::mesh::{IndexedMesh, MeshError};
+
+type Vec3 = TVec3<f32>;
+
+/// loads an ascii or binary .stl file, ascii files get one mesh per `solid`
+///
+/// the facet normals are ignored since exporters often leave them zeroed, and identical vertices are merged so normals
+/// can be smoothed across facets
+pub fn load_stl(path: &str) -> Result<Vec<(String, IndexedMesh)>, MeshError> {
+    parse_stl(path, &read(path).map_err(|x| MeshError::Io(path.to_string(), x))?)
+}
+
+/// `path` is only for errors
+fn parse_stl(path: &str, data: &[u8]) -> Result<Vec<(String, IndexedMesh)>, MeshError> {
+    let err = |x: &str| MeshError::Format(path.to_string(), x.to_string());
+
+    // binary files can start with "solid" too, so check if the triangle count fits in the file first. some exporters
+    // pad the end, so there can be more
+    let count = data.get(80..84).map(|x| u32::from_le_bytes(x.try_into().unwrap()) as usize);
+    let len = count.and_then(|x| x.checked_mul(50)?.checked_add(84));
+    if len.is_some_and(|x| data.len() >= x) || (count.is_some() && !data.trim_ascii_start().starts_with(b"solid")) {
+        let len = len.filter(|&x| data.len() >= x).ok_or_else(|| err("binary stl is shorter than its triangle count"))?;
+        let vertices = data[84..len].chunks_exact(50).flat_map(|x| {
+            // 12 bytes of normal, 3 vertices, then 2 bytes of attributes
+            (0..3).map(move |i| {
+                let v = &x[12 + i * 12..24 + i * 12];
+                Vec3::new(f32::from_le_bytes(v[0..4].try_into().unwrap()), f32::from_le_bytes(v[4..8].try_into().unwrap()), f32::from_le_bytes(v[8..12].try_into().unwrap()))
+            })
+        });
+        return Ok(vec![("".to_string(), weld(vertices))]);
+    }
+
+    let text = std::str::from_utf8(data).map_err(|_| err("not an ascii or binary stl file"))?;
+    let mut words = text.split_ascii_whitespace();
+    if words.next() != Some("solid") {
+        return Err(err("not an ascii or binary stl file"));
+    }
+    let mut solids = vec![];
+    let mut name = vec![];
+    let mut vertices = vec![];
+    // the name runs until the first facet
+    let mut in_name = true;
+    while let Some(word) = words.next() {
+        match word {
+            "vertex" => {
+                let mut v = [0.0; 3];
+                for x in &mut v {
+                    let word = words.next().ok_or_else(|| err("missing value"))?;
+                    *x = word.parse().map_err(|_| MeshError::Format(path.to_string(), format!("bad number {word:?}")))?;
+                }
+                vertices.push(Vec3::from(v));
+            },
+            "endsolid" => {
+                if vertices.len() % 3 != 0 {
+                    return Err(err("facet without 3 vertices"));
+                }
+                solids.push((name.join(" "), weld(vertices.drain(..))));
+                // skip the repeated name
+                in_name = false;
+            },
+            "solid" => {
+                name.clear();
+                in_name = true;
+            },
+            "facet" => in_name = false,
+            x if in_name => name.push(x),
+            _ => {},
+        }
+    }
+    if !vertices.is_empty() {
+        return Err(err("missing endsolid"));
+    }
+    Ok(solids)
+}
+
+/// every 3 vertices are a triangle
+fn weld(vertices: impl Iterator<Item = Vec3>) -> IndexedMesh {
+    let mut mesh = IndexedMesh::default();
+    let mut ids = HashMap::new();
+    let indices: Vec<usize> = vertices.map(|v| {
+        *ids.entry(v.map(f32::to_bits)).or_insert_with(|| {
+            mesh.positions.push(v);
+            mesh.positions.len() - 1
+        })
+    }).collect();
+    mesh.faces = indices.chunks_exact(3).map(|x| [x[0], x[1], x[2]]).collect();
+    mesh
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// `header` padded to 80 bytes, then two triangles sharing an edge
+    fn binary(header: &str, padding: usize) -> Vec<u8> {
+        let mut data = format!("{header:80}").into_bytes();
+        data.extend(2u32.to_le_bytes());
+        for tri in [[0.0, 0.0, 0.0, 1.0, 0.0, 0.0, 0.0, 1.0, 0.0], [1.0, 0.0, 0.0, 1.0, 1.0, 0.0, 0.0, 1.0, 0.0f32]] {
+            data.extend([0.0f32; 3].iter().chain(&tri).flat_map(|x| x.to_le_bytes()));
+            data.extend([0; 2]);
+        }
+        data.extend(vec![0; padding]);
+        data
+    }
+
+    #[test]
+    fn ascii_solids() {
+        let text = "solid first part\nfacet normal 0 0 1\nouter loop\nvertex 0 0 0\nvertex 1 0 0\nvertex 0 1 0\nendloop\nendfacet\nendsolid first part\n\
+                    solid second\nfacet normal 0 0 0\nouter loop\nvertex 0 0 1\nvertex 1 0 1\nvertex 0 1 1\nendloop\nendfacet\n\
+                    facet normal 0 0 0\nouter loop\nvertex 1 0 1\nvertex 1 1 1\nvertex 0 1 1\nendloop\nendfacet\nendsolid second\n";
+        let solids = parse_stl("test.stl", text.as_bytes()).unwrap();
+        assert_eq!(solids.iter().map(|x| (x.0.as_str(), x.1.faces.len(), x.1.positions.len())).collect::<Vec<_>>(), [("first part", 1, 3), ("second", 2, 4)]);
+    }
+
+    #[test]
+    fn binary_starting_with_solid() {
+        let solids = parse_stl("test.stl", &binary("solid exported by a cad program", 0)).unwrap();
+        assert_eq!(solids.len(), 1);
+        // the shared edge is welded
+        assert_eq!((solids[0].1.faces.len(), solids[0].1.positions.len()), (2, 4));
+    }
+
+    #[test]
+    fn binary_with_padding() {
+        for header in ["solid", "binary"] {
+            let solids = parse_stl("test.stl", &binary(header, 37)).unwrap();
+            assert_eq!((solids[0].1.faces.len(), solids[0].1.positions.len()), (2, 4));
+        }
+    }
+
+    #[test]
+    fn truncated_binary() {
+        let data = binary("binary", 0);
+        assert!(parse_stl("test.stl", &data[..data.len() - 10]).is_err());
+    }
+}
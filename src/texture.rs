@@ -0,0 +1,25 @@
+use std::collections::HashMap;
+
+/// every texture packed into one buffer of RGBA8 texels, still in sRGB like the files they come from
+#[derive(Default)]
+pub struct Textures {
+    /// `uvec4(first texel, width, height, 0)`
+    pub headers: Vec<[u32; 4]>,
+    /// rows from the top, each texel is `r | g << 8 | b << 16 | a << 24`
+    pub texels: Vec<u32>,
+    ids: HashMap<String, i32>,
+}
+impl Textures {
+    /// index of the texture for `path`, loading it only the first time
+    pub fn load(&mut self, path: &str) -> Result<i32, image::ImageError> {
+        if let Some(&id) = self.ids.get(path) {
+            return Ok(id);
+        }
+        let image = image::open(path)?.into_rgba8();
+        self.headers.push([self.texels.len() as u32, image.width(), image.height(), 0]);
+        self.texels.extend(image.pixels().map(|x| u32::from_le_bytes(x.0)));
+        let id = self.headers.len() as i32 - 1;
+        self.ids.insert(path.to_string(), id);
+        Ok(id)
+    }
+}
@@ -1,6 +1,6 @@
 use std::{marker::PhantomData, mem::size_of, ptr::slice_from_raw_parts_mut};
 
-use ash::vk::{self, Buffer, BufferUsageFlags, DeviceMemory, MemoryAllocateInfo, MemoryMapFlags, MemoryPropertyFlags, MemoryRequirements, SharingMode, StructureType};
+use ash::vk::{self, Buffer, BufferCopy, BufferUsageFlags, CommandBuffer, DescriptorBufferInfo, DeviceMemory, MemoryAllocateInfo, MemoryMapFlags, MemoryPropertyFlags, MemoryRequirements, SharingMode, StructureType};
 
 use super::{LogicalDevice, PhysicalDevice};
 
@@ -41,12 +41,12 @@ impl<'a, 'b, T: Sized + Copy + 'a> StagedUBO<'a, 'b, T> {
         unsafe { logical.device.bind_buffer_memory(ubo, ubo_mem, 0) }.unwrap();
 
         let map = unsafe { logical.device.map_memory(stage_mem, 0, size as u64, MemoryMapFlags::empty()) }.unwrap().cast();
-        let mut buff = Self { stage, stage_mem, ubo, ubo_mem, buffer: PhantomData, device: &logical, mem_map: map, size };
+        let mut buff = Self { stage, stage_mem, ubo, ubo_mem, buffer: PhantomData, device: logical, mem_map: map, size };
         buff.get_slice().copy_from_slice(&data);
         buff
 
     }
-    pub fn get_slice<'c>(&'c mut self) -> &'c mut [T] {
+    pub fn get_slice(&mut self) -> &mut [T] {
         unsafe {
             &mut *slice_from_raw_parts_mut(self.mem_map, self.size/size_of::<T>())
         }
@@ -85,19 +85,20 @@ pub struct StagedSSBO<'a, 'b, T> {
     mem_map: *mut T
 }
 impl<'a, 'b, T: Sized + Copy + 'a> StagedSSBO<'a, 'b, T> {
+    /// empty buffers are rounded up to one element since vulkan does not allow zero sized buffers
     pub fn new(logical: &'a LogicalDevice<'b>, len: usize) -> Self {
-        let size = size_of::<T>() * len;
+        let size = size_of::<T>() * len.max(1);
         let stage_info = vk::BufferCreateInfo {
             s_type: StructureType::BUFFER_CREATE_INFO,
             size: size as u64,
-            usage: BufferUsageFlags::TRANSFER_DST,
+            usage: BufferUsageFlags::TRANSFER_SRC | BufferUsageFlags::TRANSFER_DST,
             sharing_mode: SharingMode::EXCLUSIVE,
             ..Default::default()
         };
         let ssbo_info = vk::BufferCreateInfo {
             s_type: StructureType::BUFFER_CREATE_INFO,
             size: size as u64,
-            usage: BufferUsageFlags::STORAGE_BUFFER | BufferUsageFlags::TRANSFER_SRC,
+            usage: BufferUsageFlags::STORAGE_BUFFER | BufferUsageFlags::TRANSFER_SRC | BufferUsageFlags::TRANSFER_DST,
             sharing_mode: SharingMode::EXCLUSIVE,
             ..Default::default()
         };
@@ -111,9 +112,15 @@ impl<'a, 'b, T: Sized + Copy + 'a> StagedSSBO<'a, 'b, T> {
         unsafe { logical.device.bind_buffer_memory(ssbo, ssbo_mem, 0) }.unwrap();
 
         let map = unsafe { logical.device.map_memory(stage_mem, 0, size as u64, MemoryMapFlags::empty()) }.unwrap().cast();
-        Self { stage, stage_mem, ssbo, ssbo_mem, buffer: PhantomData, device: &logical, mem_map: map, size }
+        Self { stage, stage_mem, ssbo, ssbo_mem, buffer: PhantomData, device: logical, mem_map: map, size }
     }
-    pub fn get_slice<'c>(&'c mut self) -> &'c mut [T] {
+    /// the data still has to be copied from the stage to the ssbo before use
+    pub fn from_vec(logical: &'a LogicalDevice<'b>, data: Vec<T>) -> Self {
+        let mut buff = Self::new(logical, data.len());
+        buff.get_slice()[..data.len()].copy_from_slice(&data);
+        buff
+    }
+    pub fn get_slice(&mut self) -> &mut [T] {
         unsafe {
             &mut *slice_from_raw_parts_mut(self.mem_map, self.size/size_of::<T>())
         }
@@ -121,6 +128,17 @@ impl<'a, 'b, T: Sized + Copy + 'a> StagedSSBO<'a, 'b, T> {
     pub fn get_ssbo(&self) -> Buffer {
         self.ssbo
     }
+    /// records a copy from the stage to the ssbo
+    pub fn record_upload(&self, cmd: CommandBuffer) {
+        unsafe { self.device.device.cmd_copy_buffer(cmd, self.stage, self.ssbo, &[BufferCopy { size: self.size as u64, ..Default::default() }]) };
+    }
+    pub fn descriptor(&self) -> DescriptorBufferInfo {
+        DescriptorBufferInfo {
+            buffer: self.ssbo,
+            offset: 0,
+            range: self.size as u64,
+        }
+    }
     pub fn get_stage(&self) -> Buffer {
         self.stage
     }
@@ -145,7 +163,7 @@ impl<T> Drop for StagedSSBO<'_, '_, T> {
 fn find_vram(device: &PhysicalDevice, type_filter: u32, props: &MemoryPropertyFlags) -> usize {
     let mem_props = unsafe { device.handle.instance.get_physical_device_memory_properties(device.device) };
     for memory in 0..mem_props.memory_type_count as usize {
-        if (type_filter & (1<<memory)) != 0 && mem_props.memory_types[memory].property_flags == props.to_owned() {
+        if (type_filter & (1<<memory)) != 0 && mem_props.memory_types[memory].property_flags == *props {
             return memory;
         }
     }
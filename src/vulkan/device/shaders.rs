@@ -1,6 +1,6 @@
 use std::{io::Read, path::Path};
 
-use super::{buffer::{StagedSSBO, StagedUBO}, LogicalDevice};
+use super::{buffer::StagedUBO, LogicalDevice};
 use ash::vk::{ComputePipelineCreateInfo, DescriptorBufferInfo, DescriptorPool, DescriptorPoolCreateInfo, DescriptorPoolSize, DescriptorSet, DescriptorSetAllocateInfo, DescriptorSetLayout, DescriptorSetLayoutBinding, DescriptorSetLayoutCreateInfo, DescriptorType, PipelineCache, PipelineLayout, PipelineLayoutCreateInfo, PipelineShaderStageCreateInfo, ShaderModule, ShaderModuleCreateInfo, ShaderStageFlags, StructureType, WriteDescriptorSet};
 
 fn create_shader_module(code: &[u8], device: &LogicalDevice) -> ShaderModule {
@@ -12,23 +12,24 @@ fn create_shader_module(code: &[u8], device: &LogicalDevice) -> ShaderModule {
     };
     unsafe { device.device.create_shader_module(&info, None).unwrap() }
 }
-fn create_pipeline<A: Copy, B: Copy>(path: &Path, ubo_buffer: &StagedUBO<'_, '_, A>, ssbo_buffer: &StagedSSBO<'_, '_, B>, device: &LogicalDevice) -> (ash::vk::Pipeline, PipelineLayout, DescriptorSetLayout, DescriptorPool, DescriptorSet) {
+fn create_pipeline<A: Copy>(path: &Path, ubo_buffer: &StagedUBO<'_, '_, A>, ssbo_buffers: &[DescriptorBufferInfo], device: &LogicalDevice) -> (ash::vk::Pipeline, PipelineLayout, DescriptorSetLayout, DescriptorPool, DescriptorSet) {
     let mut buf = vec![];
     std::fs::File::open(path).unwrap().read_to_end(&mut buf).unwrap();
-    let bindings = vec![DescriptorSetLayoutBinding {
+    let mut bindings = vec![DescriptorSetLayoutBinding {
         binding: 0,
         descriptor_type: DescriptorType::UNIFORM_BUFFER,
         descriptor_count: 1,
         stage_flags: ShaderStageFlags::COMPUTE,
         ..Default::default()
-    },
-    DescriptorSetLayoutBinding {
-        binding: 1,
+    }];
+    // storage buffers are bound in order starting at binding 1
+    bindings.extend((0..ssbo_buffers.len()).map(|i| DescriptorSetLayoutBinding {
+        binding: i as u32 + 1,
         descriptor_type: DescriptorType::STORAGE_BUFFER,
         descriptor_count: 1,
         stage_flags: ShaderStageFlags::COMPUTE,
         ..Default::default()
-    }];
+    }));
     let info = DescriptorSetLayoutCreateInfo {
         s_type: StructureType::DESCRIPTOR_SET_LAYOUT_CREATE_INFO,
         binding_count: bindings.len() as u32,
@@ -46,14 +47,14 @@ fn create_pipeline<A: Copy, B: Copy>(path: &Path, ubo_buffer: &StagedUBO<'_, '_,
         ..Default::default()
     };
     let layout = unsafe { device.device.create_pipeline_layout(&info, None) }.unwrap();
-    let pool_sizes = vec![
+    let pool_sizes = [
         DescriptorPoolSize {
             ty: DescriptorType::UNIFORM_BUFFER,
             descriptor_count: 1
         },
         DescriptorPoolSize {
             ty: DescriptorType::STORAGE_BUFFER,
-            descriptor_count: 1
+            descriptor_count: ssbo_buffers.len() as u32
         }
     ];
     let info = DescriptorPoolCreateInfo {
@@ -72,36 +73,32 @@ fn create_pipeline<A: Copy, B: Copy>(path: &Path, ubo_buffer: &StagedUBO<'_, '_,
         ..Default::default()
     };
     let descriptor_set = unsafe { device.device.allocate_descriptor_sets(&info) }.unwrap()[0];
-    unsafe { device.device.update_descriptor_sets(&[
-        WriteDescriptorSet {
-            s_type: StructureType::WRITE_DESCRIPTOR_SET,
-            dst_set: descriptor_set,
-            dst_binding: 0,
-            dst_array_element: 0,
-            descriptor_count: 1,
-            descriptor_type: DescriptorType::UNIFORM_BUFFER,
-            p_buffer_info: &DescriptorBufferInfo {
-                buffer: ubo_buffer.get_ubo(),
-                offset: 0,
-                range: ubo_buffer.get_size() as u64,
-            },
-            ..Default::default()
-        },
-        WriteDescriptorSet {
-            s_type: StructureType::WRITE_DESCRIPTOR_SET,
-            dst_set: descriptor_set,
-            dst_binding: 1,
-            dst_array_element: 0,
-            descriptor_count: 1,
-            descriptor_type: DescriptorType::STORAGE_BUFFER,
-            p_buffer_info: &DescriptorBufferInfo {
-                buffer: ssbo_buffer.get_ssbo(),
-                offset: 0,
-                range: ssbo_buffer.get_size() as u64,
-            },
-            ..Default::default()
-        }
-    ], &[]) };
+    let ubo_info = DescriptorBufferInfo {
+        buffer: ubo_buffer.get_ubo(),
+        offset: 0,
+        range: ubo_buffer.get_size() as u64,
+    };
+    let mut writes = vec![WriteDescriptorSet {
+        s_type: StructureType::WRITE_DESCRIPTOR_SET,
+        dst_set: descriptor_set,
+        dst_binding: 0,
+        dst_array_element: 0,
+        descriptor_count: 1,
+        descriptor_type: DescriptorType::UNIFORM_BUFFER,
+        p_buffer_info: &ubo_info,
+        ..Default::default()
+    }];
+    writes.extend(ssbo_buffers.iter().enumerate().map(|(i, info)| WriteDescriptorSet {
+        s_type: StructureType::WRITE_DESCRIPTOR_SET,
+        dst_set: descriptor_set,
+        dst_binding: i as u32 + 1,
+        dst_array_element: 0,
+        descriptor_count: 1,
+        descriptor_type: DescriptorType::STORAGE_BUFFER,
+        p_buffer_info: info,
+        ..Default::default()
+    }));
+    unsafe { device.device.update_descriptor_sets(&writes, &[]) };
     let name = "main\x00";
     let info = [ComputePipelineCreateInfo {
         s_type: StructureType::COMPUTE_PIPELINE_CREATE_INFO,
@@ -129,8 +126,9 @@ pub struct Pipeline<'a> {
     owner: &'a LogicalDevice<'a>
 }
 impl<'a> Pipeline<'a> {
-    pub fn new<T: AsRef<Path>, A: Copy, B: Copy>(path: T, ubo_buffer: &StagedUBO<'_, '_, A>, ssbo_buffer: &StagedSSBO<'_, '_, B>, device: &'a LogicalDevice) -> Self {
-        let (pipeline, layout, descriptors, descriptor_pool, descriptor_set) = create_pipeline(path.as_ref(), ubo_buffer, ssbo_buffer, device);
+    /// `ssbo_buffers` are bound to bindings 1, 2, 3... in the order given
+    pub fn new<T: AsRef<Path>, A: Copy>(path: T, ubo_buffer: &StagedUBO<'_, '_, A>, ssbo_buffers: &[DescriptorBufferInfo], device: &'a LogicalDevice) -> Self {
+        let (pipeline, layout, descriptors, descriptor_pool, descriptor_set) = create_pipeline(path.as_ref(), ubo_buffer, ssbo_buffers, device);
         Self { pipeline, layout, descriptors, owner: device, descriptor_pool, descriptor_set }
     }
 }
@@ -17,9 +17,9 @@ impl VulkanHandle {
             s_type: ash::vk::StructureType::INSTANCE_CREATE_INFO,
             p_application_info: &ash::vk::ApplicationInfo {
                 s_type: ash::vk::StructureType::APPLICATION_INFO,
-                p_application_name: "\x00".as_ptr() as *const i8,
+                p_application_name: c"".as_ptr(),
                 application_version: ash::vk::make_api_version(0, 0, 0, 0),
-                p_engine_name: "\x00".as_ptr() as *const i8,
+                p_engine_name: c"".as_ptr(),
                 engine_version: ash::vk::make_api_version(0, 0, 0, 0),
                 api_version: ash::vk::API_VERSION_1_0,
                 ..Default::default()